@@ -0,0 +1,129 @@
+//! Subcommands that run without opening a window.
+
+use crate::merge::{self, Side};
+use crate::settings::Settings;
+use crate::storage;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+const USAGE: &str = "Usage:
+  DYBudget convert <from> <to>
+  DYBudget merge <base> <mine> <theirs> <output> [--mine | --theirs]";
+
+/// Runs the subcommand in `args`, if there is one, and returns the process
+/// exit code. `None` means the window should open as usual.
+pub fn run(args: &[String]) -> Option<i32> {
+    let command: fn(&[String]) -> Result<(), String> = match args.first().map(String::as_str)? {
+        "convert" => convert,
+        "merge" => merge,
+        "help" | "--help" | "-h" => help,
+        _ => return None,
+    };
+    #[cfg(windows)]
+    attach_console();
+    Some(match command(&args[1..]) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    })
+}
+
+/// On Windows this is a windows-subsystem program without a console of its
+/// own; borrowing the one it was started from lets output reach the terminal.
+/// The shell does not wait for such a program, so it cannot read answers
+/// from it, which is why `merge` needs `--mine` or `--theirs` there.
+#[cfg(windows)]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Fails harmlessly when there is no parent console, e.g. from Explorer.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+fn help(_: &[String]) -> Result<(), String> {
+    println!("{}", USAGE);
+    Ok(())
+}
+
+fn convert(args: &[String]) -> Result<(), String> {
+    let [from, to] = args else {
+        return Err(USAGE.to_string());
+    };
+    let count = storage::convert(from.as_ref(), to.as_ref())?;
+    println!("Converted {} ({} transactions) to {}.", from, count, to);
+    Ok(())
+}
+
+/// Three-way merge of two copies of a budget. Conflicts are settled by
+/// `--mine`/`--theirs`, or else asked about one by one (not on Windows, see
+/// `attach_console`).
+fn merge(args: &[String]) -> Result<(), String> {
+    let (base, mine, theirs, output, prefer) = match args {
+        [base, mine, theirs, output] => (base, mine, theirs, output, None),
+        [base, mine, theirs, output, flag] => {
+            let side = match flag.as_str() {
+                "--mine" => Side::Mine,
+                "--theirs" => Side::Theirs,
+                _ => return Err(USAGE.to_string()),
+            };
+            (base, mine, theirs, output, Some(side))
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+    let [base, mine, theirs] = [base, mine, theirs].map(|path| storage::load_file(Path::new(path), None));
+    let mut merge = merge::three_way(&base?, &mine?, &theirs?);
+    match prefer {
+        Some(side) => merge.choose_all(side),
+        None if cfg!(windows) && !merge.conflicts.is_empty() => {
+            return Err(format!(
+                "{} conflict(s) to settle; pass --mine or --theirs to choose a side for all of them.",
+                merge.conflicts.len()
+            ));
+        }
+        None => ask(&mut merge)?,
+    }
+    let mut state = merge.finish()?;
+    state.normalize();
+    let retention = Settings::load(&Settings::path()).backup_retention;
+    storage::open(Path::new(output), retention).save(&state)?;
+    println!(
+        "Merged into {} ({} transactions, {} conflict(s) resolved).",
+        output,
+        state.transactions.len(),
+        merge.conflicts.len()
+    );
+    Ok(())
+}
+
+fn ask(merge: &mut merge::Merge) -> Result<(), String> {
+    let total = merge.conflicts.len();
+    let mut lines = io::stdin().lock().lines();
+    for (index, conflict) in merge.conflicts.iter_mut().enumerate() {
+        println!("\nConflict {} of {}: {}", index + 1, total, conflict.title());
+        for (field, mine, theirs) in conflict.differences() {
+            let field = if field.is_empty() { String::new() } else { format!("{}: ", field) };
+            println!("  {}mine = {}, theirs = {}", field, mine, theirs);
+        }
+        conflict.choice = loop {
+            print!("Keep [m]ine or [t]heirs? ");
+            let _ = io::stdout().flush();
+            let answer = lines
+                .next()
+                .and_then(Result::ok)
+                .ok_or_else(|| "Merge cancelled; nothing was written.".to_string())?;
+            match answer.trim() {
+                "m" | "mine" => break Side::Mine,
+                "t" | "theirs" => break Side::Theirs,
+                _ => {}
+            }
+        };
+    }
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Passphrase encryption for budget files.
+//!
+//! An encrypted file is `MAGIC`, a random salt, a random nonce and then the
+//! JSON sealed with XChaCha20-Poly1305. The key comes from the passphrase
+//! through Argon2id, so guessing passphrases is slow; the header is
+//! authenticated along with the contents.
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+/// Marks an encrypted budget file; the last byte is the format version.
+const MAGIC: &[u8; 8] = b"DYBENC\x00\x01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN;
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// A key derived from a passphrase. Deriving is deliberately slow, so the
+/// key is kept and reused for every save; each save gets a fresh nonce.
+#[derive(Clone)]
+pub struct Key {
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+impl Key {
+    /// A key for `passphrase` with a new random salt.
+    pub fn new(passphrase: &str) -> Result<Self, String> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt)
+    }
+
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> Result<Self, String> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|err| format!("Could not derive a key: {}", err))?;
+        Ok(Self { salt, key })
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&self.salt);
+        header
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut out = self.header();
+        let sealed = cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: &out })
+            .map_err(|_| "Could not encrypt the budget.".to_string())?;
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    /// Opens a file sealed with this key, such as a backup of the open budget.
+    pub fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        if salt(bytes)? != self.salt {
+            return Err("it was encrypted with a different passphrase.".to_string());
+        }
+        self.open(bytes)
+    }
+
+    fn open(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        let nonce = XNonce::from_slice(&bytes[HEADER_LEN..HEADER_LEN + NONCE_LEN]);
+        let payload = Payload {
+            msg: &bytes[HEADER_LEN + NONCE_LEN..],
+            aad: &bytes[..HEADER_LEN],
+        };
+        // The tag check cannot tell a wrong passphrase from a damaged file.
+        cipher
+            .decrypt(nonce, payload)
+            .map_err(|_| "Wrong passphrase, or the file has been damaged.".to_string())
+    }
+}
+
+fn salt(bytes: &[u8]) -> Result<[u8; SALT_LEN], String> {
+    if !is_encrypted(bytes) || bytes.len() < HEADER_LEN + NONCE_LEN {
+        return Err("This is not an encrypted budget file.".to_string());
+    }
+    let mut salt = [0; SALT_LEN];
+    salt.copy_from_slice(&bytes[MAGIC.len()..HEADER_LEN]);
+    Ok(salt)
+}
+
+/// Opens an encrypted file with `passphrase`, returning the contents and the
+/// key so later saves need not derive it again.
+pub fn decrypt(bytes: &[u8], passphrase: &str) -> Result<(Vec<u8>, Key), String> {
+    let key = Key::derive(passphrase, salt(bytes)?)?;
+    let plaintext = key.open(bytes)?;
+    Ok((plaintext, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_a_wrong_passphrase() {
+        let key = Key::new("correct horse").unwrap();
+        let sealed = key.encrypt(b"{\"version\": 2}").unwrap();
+        assert!(is_encrypted(&sealed));
+
+        let (plaintext, reopened) = decrypt(&sealed, "correct horse").unwrap();
+        assert_eq!(plaintext, b"{\"version\": 2}");
+        assert_eq!(reopened.decrypt(&sealed).unwrap(), plaintext);
+        assert!(decrypt(&sealed, "battery staple").is_err());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(key.decrypt(&tampered).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Writing transactions as CSV for spreadsheets.
+
+use crate::model::{Account, Category, Transaction, category_path};
+use crate::money::Money;
+use chrono::format::{Item, StrftimeItems};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Date,
+    Time,
+    Description,
+    Amount,
+    Currency,
+    Category,
+    Account,
+    TransferTo,
+    Memo,
+    Id,
+}
+
+impl Column {
+    pub const ALL: [Column; 10] = [
+        Column::Date,
+        Column::Time,
+        Column::Description,
+        Column::Amount,
+        Column::Currency,
+        Column::Category,
+        Column::Account,
+        Column::TransferTo,
+        Column::Memo,
+        Column::Id,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Column::Date => "Date",
+            Column::Time => "Time",
+            Column::Description => "Description",
+            Column::Amount => "Amount",
+            Column::Currency => "Currency",
+            Column::Category => "Category",
+            Column::Account => "Account",
+            Column::TransferTo => "Transfer to",
+            Column::Memo => "Memo",
+            Column::Id => "Id",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Columns in the order they are written.
+    pub columns: Vec<Column>,
+    /// chrono format string such as "%d/%m/%Y".
+    pub date_format: String,
+    /// Write "1234,56" instead of "1234.56".
+    pub decimal_comma: bool,
+    pub delimiter: u8,
+    /// One row per line of a split transaction instead of one per
+    /// transaction, so categories add up in a pivot table.
+    pub split_rows: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            columns: vec![
+                Column::Date,
+                Column::Description,
+                Column::Amount,
+                Column::Category,
+                Column::Account,
+            ],
+            date_format: "%Y-%m-%d".to_string(),
+            decimal_comma: false,
+            delimiter: b',',
+            split_rows: false,
+        }
+    }
+}
+
+pub fn write(
+    transactions: &[&Transaction],
+    accounts: &[Account],
+    categories: &[Category],
+    options: &Options,
+) -> Result<String, String> {
+    if StrftimeItems::new(&options.date_format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("\"{}\" is not a valid date format.", options.date_format));
+    }
+    // Time fields such as %H pass the check above but cannot format a date,
+    // so formatting reports that instead of panicking.
+    let date = |tx: &Transaction| {
+        let mut text = String::new();
+        write!(text, "{}", tx.date.format(&options.date_format))
+            .map(|_| text)
+            .map_err(|_| format!("\"{}\" cannot format a date.", options.date_format))
+    };
+    let account = |id| accounts.iter().find(|a| a.id == id).map(|a| a.name.clone()).unwrap_or_default();
+    let category = |id: Option<_>| id.map(|id| category_path(categories, id)).unwrap_or_default();
+    let amount = |money: Money| {
+        let text = money.to_string();
+        if options.decimal_comma { text.replace('.', ",") } else { text }
+    };
+
+    let mut writer = csv::WriterBuilder::new().delimiter(options.delimiter).from_writer(Vec::new());
+    let error = |err: csv::Error| format!("Could not write the CSV file: {}", err);
+    writer
+        .write_record(options.columns.iter().map(|c| c.label()))
+        .map_err(error)?;
+    for tx in transactions {
+        // (category, amount, memo) of each row this transaction becomes.
+        let lines: Vec<(Option<_>, Money, &str)> = if options.split_rows && !tx.splits.is_empty() {
+            tx.splits.iter().map(|s| (s.category, s.amount, s.memo.as_str())).collect()
+        } else {
+            vec![(tx.category, tx.amount, "")]
+        };
+        for (line_category, line_amount, memo) in lines {
+            let mut row = Vec::with_capacity(options.columns.len());
+            for column in &options.columns {
+                row.push(match column {
+                    Column::Date => date(tx)?,
+                    Column::Time => tx.time.map(|t| t.format("%H:%M").to_string()).unwrap_or_default(),
+                    Column::Description => tx.title.clone(),
+                    Column::Amount => amount(line_amount),
+                    Column::Currency => line_amount.currency().to_string(),
+                    Column::Category if tx.splits.is_empty() || options.split_rows => category(line_category),
+                    Column::Category => {
+                        let names: Vec<String> = tx.splits.iter().map(|s| category(s.category)).collect();
+                        names.join(" + ")
+                    }
+                    Column::Account => account(tx.account),
+                    Column::TransferTo => tx.transfer_to.map(account).unwrap_or_default(),
+                    Column::Memo => memo.to_string(),
+                    Column::Id => tx.id.to_string(),
+                });
+            }
+            writer.write_record(row).map_err(error)?;
+        }
+    }
+    let bytes = writer.into_inner().map_err(|err| format!("Could not write the CSV file: {}", err))?;
+    String::from_utf8(bytes).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{AccountKind, Split};
+    use crate::money::Currency;
+    use chrono::NaiveDate;
+    use egui::Color32;
+    use uuid::Uuid;
+
+    #[test]
+    fn writes_chosen_columns_and_split_rows() {
+        let php = |cents| Money::from_cents(cents, Currency::PHP);
+        let wallet = Account::new("Wallet", AccountKind::Cash, php(0));
+        let food = Category::new("Food", Color32::WHITE, "", None);
+        let home = Category::new("Home", Color32::WHITE, "", None);
+        let split = |category: &Category, cents| Split {
+            category: Some(category.id),
+            amount: php(cents),
+            memo: String::new(),
+        };
+        let tx = Transaction {
+            id: Uuid::new_v4(),
+            title: "Market; weekly".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+            time: None,
+            amount: php(-123_450),
+            category: None,
+            account: wallet.id,
+            recurring: None,
+            splits: vec![split(&food, -100_000), split(&home, -23_450)],
+            transfer_to: None,
+            import_id: None,
+        };
+        let mut options = Options {
+            date_format: "%d.%m.%Y".to_string(),
+            decimal_comma: true,
+            delimiter: b';',
+            ..Options::default()
+        };
+        let accounts = [wallet];
+        let categories = [food, home];
+        let text = write(&[&tx], &accounts, &categories, &options).unwrap();
+        assert_eq!(
+            text,
+            "Date;Description;Amount;Category;Account\n09.03.2024;\"Market; weekly\";-1234,50;Food + Home;Wallet\n"
+        );
+
+        options.split_rows = true;
+        options.columns = vec![Column::Category, Column::Amount];
+        let text = write(&[&tx], &accounts, &categories, &options).unwrap();
+        assert_eq!(text, "Category;Amount\nFood;-1000,00\nHome;-234,50\n");
+
+        options.date_format = "%Q".to_string();
+        assert!(write(&[&tx], &accounts, &categories, &options).is_err());
+        options.columns = vec![Column::Date];
+        options.date_format = "%Y-%m-%d %H:%M".to_string();
+        assert!(write(&[&tx], &accounts, &categories, &options).is_err());
+    }
+}
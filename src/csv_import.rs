@@ -0,0 +1,276 @@
+//! Turning bank CSV statements into transactions.
+//!
+//! Banks disagree on everything, so nothing is assumed: the caller picks the
+//! delimiter, which columns hold the date, description and amount (or
+//! separate debit and credit columns) and how dates are written.
+
+use crate::model::Transaction;
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Delimiters offered in the import dialog, with their labels.
+pub const DELIMITERS: [(u8, &str); 4] = [(b',', "Comma"), (b';', "Semicolon"), (b'\t', "Tab"), (b'|', "Pipe")];
+
+/// Date formats offered in the import dialog, in chrono syntax.
+pub const DATE_FORMATS: [&str; 7] = ["%Y-%m-%d", "%m/%d/%Y", "%d/%m/%Y", "%d.%m.%Y", "%m/%d/%y", "%d/%m/%y", "%d %b %Y"];
+
+/// A statement split into cells. Every row has the same number of cells as
+/// the widest one.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    /// Column names from the header row, or "Column 1", "Column 2"…
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+pub fn read(path: &Path, delimiter: u8, has_header: bool) -> Result<Table, String> {
+    let bytes = fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    parse(&String::from_utf8_lossy(&bytes), delimiter, has_header)
+}
+
+pub fn parse(text: &str, delimiter: u8, has_header: bool) -> Result<Table, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.trim_start_matches('\u{feff}').as_bytes());
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|err| format!("Could not read the CSV file: {}", err))?;
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        rows.push(record.iter().map(str::to_string).collect());
+    }
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    for row in &mut rows {
+        row.resize(width, String::new());
+    }
+    let headers = if has_header && !rows.is_empty() {
+        rows.remove(0)
+    } else {
+        (1..=width).map(|n| format!("Column {}", n)).collect()
+    };
+    Ok(Table { headers, rows })
+}
+
+/// The delimiter that splits the first lines into the most, and equally
+/// many, columns.
+pub fn guess_delimiter(text: &str) -> u8 {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).take(5).collect();
+    DELIMITERS
+        .iter()
+        .map(|(delimiter, _)| *delimiter)
+        .max_by_key(|delimiter| {
+            let counts: Vec<usize> = lines.iter().map(|l| l.bytes().filter(|b| b == delimiter).count()).collect();
+            let consistent = counts.windows(2).all(|pair| pair[0] == pair[1]);
+            (consistent && counts.first().is_some_and(|c| *c > 0), counts.first().copied().unwrap_or(0))
+        })
+        .unwrap_or(b',')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountColumns {
+    /// One signed column; negative amounts are spending.
+    Signed(usize),
+    /// Money out and money in kept apart, both written as positive numbers.
+    DebitCredit { debit: usize, credit: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub date: usize,
+    pub description: usize,
+    pub amount: AmountColumns,
+    /// chrono format string such as "%d/%m/%Y".
+    pub date_format: String,
+    /// True for amounts written like "1.234,56".
+    pub decimal_comma: bool,
+}
+
+/// Converts every row, returning the transactions and a message for each
+/// row that could not be read (1-based row numbers of the data rows).
+pub fn transactions(table: &Table, mapping: &Mapping, account: Uuid, currency: Currency) -> (Vec<Transaction>, Vec<String>) {
+    let mut imported = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, row) in table.rows.iter().enumerate() {
+        match transaction(row, mapping, account, currency) {
+            Ok(tx) => imported.push(tx),
+            Err(err) => skipped.push(format!("Row {}: {}", index + 1, err)),
+        }
+    }
+    (imported, skipped)
+}
+
+pub fn transaction(row: &[String], mapping: &Mapping, account: Uuid, currency: Currency) -> Result<Transaction, String> {
+    let cell = |column: usize| row.get(column).map(String::as_str).unwrap_or_default();
+    let date = NaiveDate::parse_from_str(cell(mapping.date), &mapping.date_format)
+        .map_err(|_| format!("\"{}\" does not match the date format {}.", cell(mapping.date), mapping.date_format))?;
+    let amount = match mapping.amount {
+        AmountColumns::Signed(column) => parse_amount(cell(column), mapping.decimal_comma, currency)?,
+        AmountColumns::DebitCredit { debit, credit } => {
+            let debit = optional_amount(cell(debit), mapping.decimal_comma, currency)?;
+            let credit = optional_amount(cell(credit), mapping.decimal_comma, currency)?;
+            credit.abs() - debit.abs()
+        }
+    };
+    let title = cell(mapping.description);
+    Ok(Transaction {
+        id: Uuid::new_v4(),
+        title: if title.is_empty() { "Imported".to_string() } else { title.to_string() },
+        date,
+        time: None,
+        amount,
+        category: None,
+        account,
+        recurring: None,
+        splits: Vec::new(),
+        transfer_to: None,
+        import_id: None,
+    })
+}
+
+fn optional_amount(text: &str, decimal_comma: bool, currency: Currency) -> Result<Money, String> {
+    if text.trim().is_empty() {
+        Ok(Money::zero(currency))
+    } else {
+        parse_amount(text, decimal_comma, currency)
+    }
+}
+
+/// Reads amounts the way statements write them: with a currency symbol or
+/// the account's currency code before or after the number, thousands
+/// separators, "(12.00)" or "12.00-" for negatives, and optionally a decimal
+/// comma. Anything else in the cell makes it an error rather than being
+/// dropped, so "1.5E3" or "ref 12" is not misread as an amount.
+pub fn parse_amount(text: &str, decimal_comma: bool, currency: Currency) -> Result<Money, String> {
+    let invalid = || format!("\"{}\" is not an amount.", text.trim());
+    let mut rest = text.trim();
+    let mut negative = false;
+    if let Some(inner) = rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')) {
+        negative = true;
+        rest = inner.trim();
+    }
+    if let Some(inner) = rest.strip_suffix('-') {
+        negative = true;
+        rest = inner.trim_end();
+    }
+    // A sign may come before the symbol ("-₱12.00") or after it.
+    let (sign, rest) = match rest.strip_prefix(['-', '+']) {
+        Some(inner) => (&rest[..1], inner.trim_start()),
+        None => ("", rest),
+    };
+    let number: String = format!("{}{}", sign, strip_currency(rest, currency))
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\u{a0}' | '\''))
+        .collect();
+    let number = if decimal_comma { number.replace('.', "").replace(',', ".") } else { number };
+    let digits = number.strip_prefix(['-', '+']).unwrap_or(&number);
+    let numeric = digits.bytes().all(|b| b.is_ascii_digit() || b == b'.' || b == b',');
+    if !numeric || !digits.bytes().any(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let amount = Money::parse(&number, currency)?;
+    Ok(if negative { -amount } else { amount })
+}
+
+/// Cuts one currency symbol and the code of `currency` off either end.
+fn strip_currency(text: &str, currency: Currency) -> &str {
+    let symbol = |c: char| matches!(c, '$' | '¢' | '£' | '¥') || ('\u{20a0}'..='\u{20cf}').contains(&c);
+    let code = currency.as_str();
+    let mut text = text.strip_prefix(symbol).unwrap_or(text).trim_start();
+    text = text.strip_suffix(symbol).unwrap_or(text).trim_end();
+    if text.get(..3).is_some_and(|start| start.eq_ignore_ascii_case(code)) {
+        text = text[3..].trim_start();
+    }
+    if let Some(end) = text.len().checked_sub(3)
+        && text.get(end..).is_some_and(|last| last.eq_ignore_ascii_case(code))
+    {
+        text = text[..end].trim_end();
+    }
+    text
+}
+
+/// Column choices from header names such as "Date", "Description",
+/// "Amount" or "Debit"/"Credit", falling back to the first columns.
+pub fn guess_columns(headers: &[String]) -> (usize, usize, AmountColumns) {
+    let find = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|header| names.iter().any(|name| header.to_lowercase().contains(name)))
+    };
+    let date = find(&["date"]).unwrap_or(0);
+    let description = find(&["desc", "payee", "memo", "narrative", "details", "particulars"]).unwrap_or(1);
+    let amount = match (find(&["debit", "withdrawal", "money out"]), find(&["credit", "deposit", "money in"])) {
+        (Some(debit), Some(credit)) => AmountColumns::DebitCredit { debit, credit },
+        _ => AmountColumns::Signed(find(&["amount", "value"]).unwrap_or(2)),
+    };
+    (date, description, amount)
+}
+
+/// The first of `DATE_FORMATS` that reads every non-empty cell of `column`.
+pub fn guess_date_format(table: &Table, column: usize) -> Option<&'static str> {
+    let cells: Vec<&str> = table
+        .rows
+        .iter()
+        .filter_map(|row| row.get(column).map(String::as_str))
+        .filter(|cell| !cell.is_empty())
+        .take(20)
+        .collect();
+    DATE_FORMATS.into_iter().find(|format| {
+        !cells.is_empty() && cells.iter().all(|cell| NaiveDate::parse_from_str(cell, format).is_ok())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = "Date;Description;Debit;Credit\n\
+        03/01/2024;Coffee;1.234,50;\n\
+        05/01/2024;\"Salary; January\";;25.000,00\n\
+        not a date;Broken;1,00;\n";
+
+    #[test]
+    fn imports_debit_credit_statements() {
+        assert_eq!(guess_delimiter(STATEMENT), b';');
+        let table = parse(STATEMENT, b';', true).unwrap();
+        assert_eq!(table.rows.len(), 3);
+        let (date, description, amount) = guess_columns(&table.headers);
+        assert_eq!(amount, AmountColumns::DebitCredit { debit: 2, credit: 3 });
+        assert_eq!(guess_date_format(&table, date), None);
+
+        let mapping = Mapping {
+            date,
+            description,
+            amount,
+            date_format: "%d/%m/%Y".to_string(),
+            decimal_comma: true,
+        };
+        let (imported, skipped) = transactions(&table, &mapping, Uuid::nil(), Currency::PHP);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(imported[0].amount, Money::from_cents(-123_450, Currency::PHP));
+        assert_eq!(imported[0].date, NaiveDate::from_ymd_opt(2024, 1, 3).unwrap());
+        assert_eq!(imported[1].title, "Salary; January");
+        assert_eq!(imported[1].amount, Money::from_cents(2_500_000, Currency::PHP));
+    }
+
+    #[test]
+    fn reads_statement_style_amounts() {
+        let php = |cents| Money::from_cents(cents, Currency::PHP);
+        assert_eq!(parse_amount("(12.00)", false, Currency::PHP), Ok(php(-1200)));
+        assert_eq!(parse_amount("₱1,234.56", false, Currency::PHP), Ok(php(123_456)));
+        assert_eq!(parse_amount("7.50-", false, Currency::PHP), Ok(php(-750)));
+        assert_eq!(parse_amount("-₱ 1 234,50", true, Currency::PHP), Ok(php(-123_450)));
+        assert_eq!(parse_amount("PHP 20.00", false, Currency::PHP), Ok(php(2000)));
+        assert_eq!(parse_amount("20.00 php", false, Currency::PHP), Ok(php(2000)));
+        assert_eq!(parse_amount("$5", false, Currency::PHP), Ok(php(500)));
+        for text in ["n/a", "1.5E3", "ref 12", "12 USD", "12abc", "PHP", "-", "1-2"] {
+            assert!(parse_amount(text, false, Currency::PHP).is_err(), "{:?} parsed", text);
+        }
+    }
+}
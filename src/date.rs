@@ -0,0 +1,294 @@
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const DISPLAY_FORMAT: &str = "%b %d, %Y";
+const DATE_FORMATS: [&str; 4] = ["%Y-%m-%d", "%b %d, %Y", "%B %d, %Y", "%b %d %Y"];
+const TIME_FORMATS: [&str; 4] = ["%H:%M", "%H:%M:%S", "%I:%M %p", "%I:%M%p"];
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// Current local time truncated to the minute, which is all the UI shows.
+pub fn now_time() -> NaiveTime {
+    let now = Local::now().time();
+    NaiveTime::from_hms_opt(now.hour(), now.minute(), 0).unwrap_or(now)
+}
+
+/// Parses user input such as "Today", "yesterday 18:30", "2023-08-31",
+/// "2023-08-31T09:15" or "Aug 31, 2023" into a date and optional time.
+/// Relative words are resolved against `today`.
+pub fn parse_date(input: &str, today: NaiveDate) -> Result<(NaiveDate, Option<NaiveTime>), String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Enter a date.".to_string());
+    }
+
+    let lower = input.to_ascii_lowercase();
+    for (word, offset) in [("today", 0), ("yesterday", 1)] {
+        if let Some(rest) = lower.strip_prefix(word) {
+            let date = today - Duration::days(offset);
+            let rest = rest.trim();
+            if rest.is_empty() {
+                return Ok((date, None));
+            }
+            return parse_time(rest)
+                .map(|time| (date, Some(time)))
+                .ok_or_else(|| format!("Unrecognised time \"{}\".", rest));
+        }
+    }
+
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(input, format) {
+            return Ok((dt.date(), Some(dt.time())));
+        }
+    }
+
+    for format in DATE_FORMATS {
+        if let Ok(date) = NaiveDate::parse_from_str(input, format) {
+            return Ok((date, None));
+        }
+    }
+
+    // "Aug 31, 2023 14:30": try splitting a trailing time off the date.
+    let words: Vec<&str> = input.split_whitespace().collect();
+    for time_words in [2, 1] {
+        if words.len() <= time_words {
+            continue;
+        }
+        let (date_part, time_part) = words.split_at(words.len() - time_words);
+        let Some(time) = parse_time(&time_part.join(" ")) else {
+            continue;
+        };
+        let date_part = date_part.join(" ");
+        for format in DATE_FORMATS {
+            if let Ok(date) = NaiveDate::parse_from_str(&date_part, format) {
+                return Ok((date, Some(time)));
+            }
+        }
+    }
+
+    Err(format!(
+        "Unrecognised date \"{}\". Use Today, Yesterday, YYYY-MM-DD or Mon DD, YYYY.",
+        input
+    ))
+}
+
+fn parse_time(input: &str) -> Option<NaiveTime> {
+    let upper = input.trim().to_ascii_uppercase();
+    TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(&upper, format).ok())
+}
+
+pub fn format_date(date: NaiveDate, time: Option<NaiveTime>) -> String {
+    match time {
+        Some(time) => format!("{} {}", date.format(DISPLAY_FORMAT), time.format("%H:%M")),
+        None => date.format(DISPLAY_FORMAT).to_string(),
+    }
+}
+
+/// Rewrites free-text `date` fields from files written before dates were typed
+/// ("Today", "Aug 31, 2023", ...) into ISO dates with an optional `time`.
+/// Relative words are resolved against `reference`, normally the day the file
+/// was last written. Text that is not a date at all moves to the end of the
+/// title and the transaction is dated `reference`; the ids of those
+/// transactions are returned so they can be pointed out.
+pub fn migrate_legacy_dates(state: &mut Value, reference: NaiveDate) -> Vec<Uuid> {
+    let Some(transactions) = state.get_mut("transactions").and_then(Value::as_array_mut) else {
+        return Vec::new();
+    };
+
+    let mut unreadable = Vec::new();
+    for tx in transactions {
+        let Some(obj) = tx.as_object_mut() else {
+            continue;
+        };
+        let Some(raw) = obj.get("date").and_then(Value::as_str) else {
+            continue;
+        };
+        if NaiveDate::parse_from_str(raw, "%Y-%m-%d").is_ok() {
+            continue;
+        }
+        let (date, time) = match parse_date(raw, reference) {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                // Keep the transaction and what was typed; only the date is a guess.
+                let title = obj.get("title").and_then(Value::as_str).unwrap_or_default();
+                let title = format!("{} [date: {}]", title, raw).trim_start().to_string();
+                obj.insert("title".to_string(), Value::String(title));
+                let id = match obj.get("id").and_then(Value::as_str).and_then(|id| id.parse().ok()) {
+                    Some(id) if id != Uuid::nil() => id,
+                    _ => {
+                        let id = Uuid::new_v4();
+                        obj.insert("id".to_string(), Value::String(id.to_string()));
+                        id
+                    }
+                };
+                unreadable.push(id);
+                (reference, None)
+            }
+        };
+        obj.insert("date".to_string(), Value::String(date.format("%Y-%m-%d").to_string()));
+        if let Some(time) = time {
+            obj.insert("time".to_string(), Value::String(time.format("%H:%M:%S").to_string()));
+        }
+    }
+    unreadable
+}
+
+/// A calendar month, the unit budgets are planned in. Serialized as "YYYY-MM".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Period {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl Period {
+    pub fn of(date: NaiveDate) -> Self {
+        Self {
+            year: date.year(),
+            month: date.month(),
+        }
+    }
+
+    pub fn current() -> Self {
+        Self::of(today())
+    }
+
+    pub fn next(self) -> Self {
+        if self.month == 12 {
+            Self { year: self.year + 1, month: 1 }
+        } else {
+            Self { year: self.year, month: self.month + 1 }
+        }
+    }
+
+    pub fn prev(self) -> Self {
+        if self.month == 1 {
+            Self { year: self.year - 1, month: 12 }
+        } else {
+            Self { year: self.year, month: self.month - 1 }
+        }
+    }
+
+    pub fn contains(self, date: NaiveDate) -> bool {
+        Self::of(date) == self
+    }
+
+    /// "March 2024", for headings.
+    pub fn label(self) -> String {
+        NaiveDate::from_ymd_opt(self.year, self.month, 1)
+            .map(|d| d.format("%B %Y").to_string())
+            .unwrap_or_else(|| self.to_string())
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (year, month) = s
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid period \"{}\".", s))?;
+        let year = year.parse().map_err(|_| format!("Invalid period \"{}\".", s))?;
+        let month = month.parse().map_err(|_| format!("Invalid period \"{}\".", s))?;
+        if !(1..=12).contains(&month) {
+            return Err(format!("Invalid period \"{}\".", s));
+        }
+        Ok(Self { year, month })
+    }
+}
+
+impl Serialize for Period {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Period {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    #[test]
+    fn parses_relative_words() {
+        let today = day(2024, 3, 1);
+        assert_eq!(parse_date("Today", today), Ok((today, None)));
+        assert_eq!(parse_date(" yesterday ", today), Ok((day(2024, 2, 29), None)));
+        assert_eq!(parse_date("yesterday 18:30", today), Ok((day(2024, 2, 29), time(18, 30))));
+        assert_eq!(parse_date("today 6:05 pm", today), Ok((today, time(18, 5))));
+        assert!(parse_date("today noon", today).unwrap_err().contains("time"));
+    }
+
+    #[test]
+    fn parses_each_date_form() {
+        let today = day(2024, 3, 1);
+        let expected = day(2023, 8, 31);
+        for input in ["2023-08-31", "Aug 31, 2023", "August 31, 2023", "Aug 31 2023"] {
+            assert_eq!(parse_date(input, today), Ok((expected, None)), "{}", input);
+        }
+        for input in [
+            "2023-08-31T09:15",
+            "2023-08-31T09:15:00",
+            "2023-08-31 09:15",
+            "2023-08-31 09:15:00",
+            "Aug 31, 2023 09:15",
+            "Aug 31, 2023 9:15 AM",
+            "2023-08-31 9:15am",
+        ] {
+            assert_eq!(parse_date(input, today), Ok((expected, time(9, 15))), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_other_input() {
+        let today = day(2024, 3, 1);
+        assert_eq!(parse_date("  ", today), Err("Enter a date.".to_string()));
+        for input in ["31/08/2023", "2023-02-30", "tomorrow", "Aug 31, 2023 25:00"] {
+            assert!(parse_date(input, today).is_err(), "{:?} parsed", input);
+        }
+    }
+
+    #[test]
+    fn keeps_legacy_text_it_cannot_read() {
+        let today = day(2024, 3, 1);
+        let mut state = serde_json::json!({
+            "transactions": [
+                { "title": "Coffee", "date": "Aug 31, 2023" },
+                { "title": "Rent", "date": "first of the month" },
+            ]
+        });
+        let unreadable = migrate_legacy_dates(&mut state, today);
+        assert_eq!(state["transactions"][0]["date"], "2023-08-31");
+        let rent = &state["transactions"][1];
+        assert_eq!(rent["date"], "2024-03-01");
+        assert_eq!(rent["title"], "Rent [date: first of the month]");
+        assert_eq!(unreadable, vec![rent["id"].as_str().unwrap().parse::<Uuid>().unwrap()]);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::model::{Account, BudgetData, BudgetPlan, Category, Transaction};
+use crate::recurring::RecurringRule;
+use uuid::Uuid;
+
+/// How many steps of undo are kept in memory.
+const HISTORY_LIMIT: usize = 200;
+
+/// A reversible change to `BudgetData`. Every mutation made from the UI goes
+/// through one of these so it can be undone and redone.
+#[derive(Debug, Clone)]
+pub enum Command {
+    AddTransaction(Transaction),
+    EditTransaction { before: Transaction, after: Transaction },
+    DeleteTransaction { tx: Transaction, index: usize },
+    SetBudget { before: BudgetPlan, after: BudgetPlan },
+    AddAccount(Account),
+    EditAccount { before: Account, after: Account },
+    DeleteAccount { account: Account, index: usize },
+    AddCategory(Category),
+    EditCategory { before: Category, after: Category },
+    DeleteCategory { category: Category, index: usize },
+    AddRule(RecurringRule),
+    EditRule { before: RecurringRule, after: RecurringRule },
+    DeleteRule { rule: RecurringRule, index: usize },
+    /// Several commands applied and reverted as one step.
+    Batch { label: String, commands: Vec<Command> },
+}
+
+/// A record a command changes, so storage can write just that record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Touched {
+    Budget,
+    Account(Uuid),
+    Category(Uuid),
+    Transaction(Uuid),
+    Rule(Uuid),
+}
+
+impl Command {
+    /// Records this command changes, whether it is applied or reverted.
+    pub fn touched(&self) -> Vec<Touched> {
+        match self {
+            Command::AddTransaction(tx) | Command::DeleteTransaction { tx, .. } => vec![Touched::Transaction(tx.id)],
+            Command::EditTransaction { after, .. } => vec![Touched::Transaction(after.id)],
+            Command::SetBudget { .. } => vec![Touched::Budget],
+            Command::AddAccount(account) | Command::DeleteAccount { account, .. } => vec![Touched::Account(account.id)],
+            Command::EditAccount { after, .. } => vec![Touched::Account(after.id)],
+            Command::AddCategory(category) | Command::DeleteCategory { category, .. } => {
+                vec![Touched::Category(category.id)]
+            }
+            Command::EditCategory { after, .. } => vec![Touched::Category(after.id)],
+            Command::AddRule(rule) | Command::DeleteRule { rule, .. } => vec![Touched::Rule(rule.id)],
+            Command::EditRule { after, .. } => vec![Touched::Rule(after.id)],
+            Command::Batch { commands, .. } => commands.iter().flat_map(Command::touched).collect(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Command::AddTransaction(tx) => format!("Add \"{}\"", tx.title),
+            Command::EditTransaction { after, .. } => format!("Edit \"{}\"", after.title),
+            Command::DeleteTransaction { tx, .. } => format!("Delete \"{}\"", tx.title),
+            Command::SetBudget { .. } => "Change budget".to_string(),
+            Command::AddAccount(account) => format!("Add account \"{}\"", account.name),
+            Command::EditAccount { after, .. } => format!("Edit account \"{}\"", after.name),
+            Command::DeleteAccount { account, .. } => format!("Delete account \"{}\"", account.name),
+            Command::AddCategory(category) => format!("Add category \"{}\"", category.name),
+            Command::EditCategory { before, after } if before.archived != after.archived => {
+                let verb = if after.archived { "Archive" } else { "Restore" };
+                format!("{} category \"{}\"", verb, after.name)
+            }
+            Command::EditCategory { after, .. } => format!("Edit category \"{}\"", after.name),
+            Command::DeleteCategory { category, .. } => format!("Delete category \"{}\"", category.name),
+            Command::AddRule(rule) => format!("Add recurring \"{}\"", rule.title),
+            Command::EditRule { after, .. } => format!("Edit recurring \"{}\"", after.title),
+            Command::DeleteRule { rule, .. } => format!("Delete recurring \"{}\"", rule.title),
+            Command::Batch { label, .. } => label.clone(),
+        }
+    }
+
+    pub fn apply(&self, data: &mut BudgetData) {
+        match self {
+            Command::AddTransaction(tx) => data.transactions.push(tx.clone()),
+            Command::EditTransaction { after, .. } => replace(&mut data.transactions, after, |t| t.id),
+            Command::DeleteTransaction { tx, .. } => data.transactions.retain(|t| t.id != tx.id),
+            Command::SetBudget { after, .. } => data.budget = after.clone(),
+            Command::AddAccount(account) => data.accounts.push(account.clone()),
+            Command::EditAccount { after, .. } => replace(&mut data.accounts, after, |a| a.id),
+            Command::DeleteAccount { account, .. } => data.accounts.retain(|a| a.id != account.id),
+            Command::AddCategory(category) => data.categories.push(category.clone()),
+            Command::EditCategory { after, .. } => replace(&mut data.categories, after, |c| c.id),
+            Command::DeleteCategory { category, .. } => data.categories.retain(|c| c.id != category.id),
+            Command::AddRule(rule) => data.recurring.push(rule.clone()),
+            Command::EditRule { after, .. } => replace(&mut data.recurring, after, |r| r.id),
+            Command::DeleteRule { rule, .. } => data.recurring.retain(|r| r.id != rule.id),
+            Command::Batch { commands, .. } => commands.iter().for_each(|c| c.apply(data)),
+        }
+    }
+
+    pub fn revert(&self, data: &mut BudgetData) {
+        match self {
+            Command::AddTransaction(tx) => data.transactions.retain(|t| t.id != tx.id),
+            Command::EditTransaction { before, .. } => replace(&mut data.transactions, before, |t| t.id),
+            Command::DeleteTransaction { tx, index } => insert_at(&mut data.transactions, *index, tx),
+            Command::SetBudget { before, .. } => data.budget = before.clone(),
+            Command::AddAccount(account) => data.accounts.retain(|a| a.id != account.id),
+            Command::EditAccount { before, .. } => replace(&mut data.accounts, before, |a| a.id),
+            Command::DeleteAccount { account, index } => insert_at(&mut data.accounts, *index, account),
+            Command::AddCategory(category) => data.categories.retain(|c| c.id != category.id),
+            Command::EditCategory { before, .. } => replace(&mut data.categories, before, |c| c.id),
+            Command::DeleteCategory { category, index } => insert_at(&mut data.categories, *index, category),
+            Command::AddRule(rule) => data.recurring.retain(|r| r.id != rule.id),
+            Command::EditRule { before, .. } => replace(&mut data.recurring, before, |r| r.id),
+            Command::DeleteRule { rule, index } => insert_at(&mut data.recurring, *index, rule),
+            Command::Batch { commands, .. } => commands.iter().rev().for_each(|c| c.revert(data)),
+        }
+    }
+}
+
+fn replace<T: Clone, K: PartialEq>(items: &mut [T], item: &T, key: impl Fn(&T) -> K) {
+    if let Some(slot) = items.iter_mut().find(|existing| key(existing) == key(item)) {
+        *slot = item.clone();
+    }
+}
+
+fn insert_at<T: Clone>(items: &mut Vec<T>, index: usize, item: &T) {
+    items.insert(index.min(items.len()), item.clone());
+}
+
+/// Undo and redo stacks of applied commands.
+#[derive(Debug, Default)]
+pub struct History {
+    done: Vec<Command>,
+    undone: Vec<Command>,
+}
+
+impl History {
+    /// Applies `command` and records it, discarding anything that was undone.
+    pub fn execute(&mut self, command: Command, data: &mut BudgetData) {
+        command.apply(data);
+        self.done.push(command);
+        self.undone.clear();
+        if self.done.len() > HISTORY_LIMIT {
+            self.done.remove(0);
+        }
+    }
+
+    /// Reverts the latest command and returns it.
+    pub fn undo(&mut self, data: &mut BudgetData) -> Option<&Command> {
+        let command = self.done.pop()?;
+        command.revert(data);
+        self.undone.push(command);
+        self.undone.last()
+    }
+
+    /// Re-applies the latest undone command and returns it.
+    pub fn redo(&mut self, data: &mut BudgetData) -> Option<&Command> {
+        let command = self.undone.pop()?;
+        command.apply(data);
+        self.done.push(command);
+        self.done.last()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Labels of applied commands, oldest first.
+    pub fn done_labels(&self) -> Vec<String> {
+        self.done.iter().map(Command::label).collect()
+    }
+
+    /// Labels of undone commands, next to be redone first.
+    pub fn undone_labels(&self) -> Vec<String> {
+        self.undone.iter().rev().map(Command::label).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::AccountKind;
+    use crate::money::{Currency, Money};
+    use chrono::NaiveDate;
+
+    fn tx(title: &str, account: &Account) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            title: title.to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            time: None,
+            amount: Money::from_cents(-1000, Currency::PHP),
+            category: None,
+            account: account.id,
+            recurring: None,
+            splits: Vec::new(),
+            transfer_to: None,
+            import_id: None,
+        }
+    }
+
+    fn data() -> BudgetData {
+        let wallet = Account::new("Wallet", AccountKind::Cash, Money::zero(Currency::PHP));
+        BudgetData {
+            transactions: ["Rent", "Food", "Fare"].map(|title| tx(title, &wallet)).to_vec(),
+            accounts: vec![wallet],
+            ..BudgetData::default()
+        }
+    }
+
+    fn titles(data: &BudgetData) -> Vec<&str> {
+        data.transactions.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    #[test]
+    fn restores_a_deleted_transaction_at_its_index() {
+        let mut data = data();
+        let mut history = History::default();
+        let food = data.transactions[1].clone();
+        history.execute(Command::DeleteTransaction { tx: food, index: 1 }, &mut data);
+        assert_eq!(titles(&data), ["Rent", "Fare"]);
+
+        assert_eq!(history.undo(&mut data).map(Command::label).as_deref(), Some("Delete \"Food\""));
+        assert_eq!(titles(&data), ["Rent", "Food", "Fare"]);
+        history.redo(&mut data);
+        assert_eq!(titles(&data), ["Rent", "Fare"]);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undoes_and_redoes_a_batch_as_one_step() {
+        let mut data = data();
+        let mut history = History::default();
+        let rent = data.transactions[0].clone();
+        let fare = data.transactions[2].clone();
+        let card = Account::new("Card", AccountKind::CreditCard, Money::zero(Currency::PHP));
+        let batch = Command::Batch {
+            label: "Tidy up".to_string(),
+            commands: vec![
+                Command::AddAccount(card.clone()),
+                Command::EditTransaction {
+                    before: rent.clone(),
+                    after: Transaction {
+                        title: "Rent (March)".to_string(),
+                        account: card.id,
+                        ..rent.clone()
+                    },
+                },
+                Command::DeleteTransaction { tx: fare, index: 2 },
+            ],
+        };
+        assert_eq!(
+            batch.touched(),
+            [Touched::Account(card.id), Touched::Transaction(rent.id), Touched::Transaction(data.transactions[2].id)]
+        );
+        history.execute(batch, &mut data);
+        assert_eq!(titles(&data), ["Rent (March)", "Food"]);
+        assert_eq!(data.accounts.len(), 2);
+        assert_eq!(history.done_labels(), ["Tidy up"]);
+
+        history.undo(&mut data);
+        assert_eq!(titles(&data), ["Rent", "Food", "Fare"]);
+        assert_eq!(data.transactions[0].account, data.accounts[0].id);
+        assert_eq!(data.accounts.len(), 1);
+        assert_eq!(history.undone_labels(), ["Tidy up"]);
+
+        history.redo(&mut data);
+        assert_eq!(titles(&data), ["Rent (March)", "Food"]);
+        assert_eq!(data.transactions[0].account, card.id);
+        assert!(history.can_undo() && !history.can_redo());
+    }
+}
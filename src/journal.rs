@@ -0,0 +1,652 @@
+//! Plain-text accounting exports: ledger/hledger journals and beancount files.
+//!
+//! Accounts become `Assets:…` and `Liabilities:…` accounts, categories
+//! become `Expenses:…` for spending and `Income:…` for money coming in, and
+//! opening balances come from `Equity:Opening-Balances`. The monthly budget
+//! is written as periodic transactions (`~ monthly` in ledger, Fava's
+//! `custom "budget"` on an opened `Expenses:Budget` in beancount); rollover
+//! has no counterpart and is left out.
+//!
+//! Each syntax has a small reader of its own, and `export` refuses to hand
+//! out text that does not read back as the journal it was written from.
+
+use crate::date::{Period, today};
+use crate::model::{AccountKind, PersistedState, category_path};
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+const OPENING_BALANCES: &str = "Equity:Opening-Balances";
+/// The account the monthly budget limits, with everything under it.
+const BUDGET_ACCOUNT: &str = "Expenses";
+/// Beancount has no bare root accounts, so its budget gets one of its own.
+const BEANCOUNT_BUDGET_ACCOUNT: &str = "Expenses:Budget";
+/// Where budgeted money notionally comes from in ledger's periodic transactions.
+const BUDGET_SOURCE: &str = "Assets";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Ledger,
+    Beancount,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub account: String,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub date: NaiveDate,
+    pub payee: String,
+    pub postings: Vec<Posting>,
+}
+
+/// The budget from `from` until the next budget for the same account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    pub from: Period,
+    pub account: String,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Journal {
+    /// Every account the entries use, sorted.
+    pub accounts: Vec<String>,
+    pub entries: Vec<Entry>,
+    pub budgets: Vec<Budget>,
+}
+
+/// The budget as `syntax`, checked to read back unchanged.
+pub fn export(state: &PersistedState, syntax: Syntax) -> Result<String, String> {
+    let journal = Journal::from_state(state, syntax);
+    let text = journal.render(syntax);
+    let read_back = parse(&text, syntax).map_err(|err| format!("The export does not read back: {}", err))?;
+    if read_back != journal {
+        return Err("The export does not read back as the same journal; nothing was written.".to_string());
+    }
+    Ok(text)
+}
+
+impl Journal {
+    pub fn from_state(state: &PersistedState, syntax: Syntax) -> Journal {
+        let mut accounts: BTreeMap<uuid::Uuid, String> = BTreeMap::new();
+        for account in &state.accounts {
+            let root = match account.kind {
+                AccountKind::Cash => "Assets:Cash",
+                AccountKind::Bank => "Assets:Bank",
+                AccountKind::EWallet => "Assets:E-Wallet",
+                AccountKind::CreditCard => "Liabilities:Credit-Card",
+            };
+            let base = format!("{}:{}", root, component(&account.name, syntax));
+            let mut name = base.clone();
+            let mut n = 2;
+            while accounts.values().any(|used| *used == name) {
+                name = format!("{}-{}", base, n);
+                n += 1;
+            }
+            accounts.insert(account.id, name);
+        }
+        let account = |id| accounts.get(&id).cloned().unwrap_or_else(|| "Assets:Unknown".to_string());
+        let category = |id: Option<uuid::Uuid>, amount: Money| {
+            let root = if amount.is_positive() { "Income" } else { "Expenses" };
+            let path = id.map(|id| category_path(&state.categories, id)).unwrap_or_default();
+            let parts: Vec<String> = path
+                .split(" / ")
+                .filter(|p| !p.is_empty())
+                .map(|p| component(p, syntax))
+                .collect();
+            if parts.is_empty() {
+                format!("{}:Uncategorized", root)
+            } else {
+                format!("{}:{}", root, parts.join(":"))
+            }
+        };
+
+        let mut transactions: Vec<_> = state.transactions.iter().collect();
+        transactions.sort_by_key(|t| (t.date, t.time));
+        let start = transactions.first().map_or_else(today, |t| t.date);
+        let mut entries = Vec::new();
+        for acc in state.accounts.iter().filter(|a| !a.opening_balance.is_zero()) {
+            entries.push(Entry {
+                date: start,
+                payee: "Opening balance".to_string(),
+                postings: vec![
+                    Posting {
+                        account: account(acc.id),
+                        amount: acc.opening_balance,
+                    },
+                    Posting {
+                        account: OPENING_BALANCES.to_string(),
+                        amount: -acc.opening_balance,
+                    },
+                ],
+            });
+        }
+        for tx in transactions {
+            let mut postings = Vec::new();
+            match tx.transfer_to {
+                Some(to) => {
+                    postings.push(Posting {
+                        account: account(to),
+                        amount: tx.amount,
+                    });
+                    postings.push(Posting {
+                        account: account(tx.account),
+                        amount: -tx.amount,
+                    });
+                }
+                None => {
+                    for (id, amount) in tx.lines() {
+                        postings.push(Posting {
+                            account: category(id, amount),
+                            amount: -amount,
+                        });
+                    }
+                    postings.push(Posting {
+                        account: account(tx.account),
+                        amount: tx.amount,
+                    });
+                }
+            }
+            entries.push(Entry {
+                date: tx.date,
+                payee: payee(&tx.title, syntax),
+                postings,
+            });
+        }
+
+        let budget_account = match syntax {
+            Syntax::Ledger => BUDGET_ACCOUNT,
+            Syntax::Beancount => BEANCOUNT_BUDGET_ACCOUNT,
+        };
+        let budgets = budgets(state, Period::of(start), budget_account);
+        let mut names: Vec<String> = entries
+            .iter()
+            .flat_map(|e| e.postings.iter().map(|p| p.account.clone()))
+            .collect();
+        if syntax == Syntax::Beancount && !budgets.is_empty() {
+            names.push(BEANCOUNT_BUDGET_ACCOUNT.to_string());
+        }
+        names.sort();
+        names.dedup();
+        Journal {
+            accounts: names,
+            entries,
+            budgets,
+        }
+    }
+
+    pub fn render(&self, syntax: Syntax) -> String {
+        match syntax {
+            Syntax::Ledger => self.render_ledger(),
+            Syntax::Beancount => self.render_beancount(),
+        }
+    }
+
+    fn render_ledger(&self) -> String {
+        let mut out = String::from("; Exported from DYBudget\n\n");
+        for account in &self.accounts {
+            out.push_str(&format!("account {}\n", account));
+        }
+        for (index, budget) in self.budgets.iter().enumerate() {
+            let until = self.budgets[index + 1..]
+                .iter()
+                .find(|b| b.account == budget.account)
+                .map(|b| format!(" to {}", first_day(b.from)))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "\n~ monthly from {}{}\n    {}  {} {}\n    {}\n",
+                first_day(budget.from),
+                until,
+                budget.account,
+                budget.amount,
+                budget.amount.currency(),
+                BUDGET_SOURCE
+            ));
+        }
+        for entry in &self.entries {
+            out.push_str(&format!("\n{} * {}\n", entry.date, entry.payee));
+            for posting in &entry.postings {
+                out.push_str(&format!(
+                    "    {}  {} {}\n",
+                    posting.account,
+                    posting.amount,
+                    posting.amount.currency()
+                ));
+            }
+        }
+        out
+    }
+
+    fn render_beancount(&self) -> String {
+        let mut out = String::from("; Exported from DYBudget\n");
+        let currency = self
+            .entries
+            .iter()
+            .flat_map(|e| e.postings.first())
+            .map(|p| p.amount.currency())
+            .chain(self.budgets.iter().map(|b| b.amount.currency()))
+            .next()
+            .unwrap_or_default();
+        out.push_str(&format!("option \"operating_currency\" \"{}\"\n\n", currency));
+        // Budgets may start before the first entry; accounts open before either.
+        let opened = self
+            .entries
+            .iter()
+            .map(|e| e.date)
+            .chain(self.budgets.iter().map(|b| first_day(b.from)))
+            .min()
+            .unwrap_or_else(today);
+        for account in &self.accounts {
+            out.push_str(&format!("{} open {}\n", opened, account));
+        }
+        if !self.budgets.is_empty() {
+            out.push('\n');
+        }
+        for budget in &self.budgets {
+            out.push_str(&format!(
+                "{} custom \"budget\" {} \"monthly\" {} {}\n",
+                first_day(budget.from),
+                budget.account,
+                budget.amount,
+                budget.amount.currency()
+            ));
+        }
+        for entry in &self.entries {
+            out.push_str(&format!("\n{} * {}\n", entry.date, quote(&entry.payee)));
+            for posting in &entry.postings {
+                out.push_str(&format!(
+                    "  {}  {} {}\n",
+                    posting.account,
+                    posting.amount,
+                    posting.amount.currency()
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// Change points of the monthly budget from `start` on: the default amount,
+/// then each month whose amount differs from the one before.
+fn budgets(state: &PersistedState, start: Period, account: &str) -> Vec<Budget> {
+    let start = state.period_budgets.keys().next().map_or(start, |first| (*first).min(start));
+    let end = state.period_budgets.keys().next_back().map_or(start, |last| last.next());
+    let mut budgets: Vec<Budget> = Vec::new();
+    let mut period = start;
+    loop {
+        let amount = state.period_budgets.get(&period).copied().unwrap_or(state.monthly_budget);
+        if budgets.last().is_none_or(|b| b.amount != amount) {
+            budgets.push(Budget {
+                from: period,
+                account: account.to_string(),
+                amount,
+            });
+        }
+        if period >= end {
+            break;
+        }
+        period = period.next();
+    }
+    budgets
+}
+
+fn first_day(period: Period) -> NaiveDate {
+    NaiveDate::from_ymd_opt(period.year, period.month, 1).unwrap_or_default()
+}
+
+/// One level of an account name. Ledger allows spaces but not `:` or runs
+/// of spaces; beancount wants capitalised words of letters, digits and `-`.
+fn component(name: &str, syntax: Syntax) -> String {
+    let text = match syntax {
+        Syntax::Ledger => name.replace([':', ';'], "-").split_whitespace().collect::<Vec<_>>().join(" "),
+        Syntax::Beancount => name
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map(|c| c.to_ascii_uppercase()).into_iter().chain(chars).collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("-"),
+    };
+    if text.is_empty() { "Unnamed".to_string() } else { text }
+}
+
+/// A title on one line with single spaces. Ledger ends a description at
+/// `;`, so it becomes a comma there; beancount quotes it instead.
+fn payee(title: &str, syntax: Syntax) -> String {
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    match syntax {
+        Syntax::Ledger => title.replace(';', ","),
+        Syntax::Beancount => title,
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads a journal in `syntax`, checking that every entry balances and, for
+/// beancount, that accounts are opened before they are used.
+pub fn parse(text: &str, syntax: Syntax) -> Result<Journal, String> {
+    let mut reader = Reader {
+        syntax,
+        journal: Journal::default(),
+        opened: BTreeMap::new(),
+        current: None,
+    };
+    for (index, line) in text.lines().enumerate() {
+        reader.line(line).map_err(|err| format!("line {}: {}", index + 1, err))?;
+    }
+    reader.finish()?;
+    let mut journal = reader.journal;
+    if syntax == Syntax::Beancount {
+        journal.accounts = reader.opened.into_keys().collect();
+    }
+    journal.accounts.sort();
+    journal.accounts.dedup();
+    Ok(journal)
+}
+
+/// What the indented lines under a header belong to.
+enum Block {
+    Entry(Entry, Vec<(String, Option<Money>)>),
+    Periodic(Period, Vec<(String, Option<Money>)>),
+    Other,
+}
+
+struct Reader {
+    syntax: Syntax,
+    journal: Journal,
+    /// Beancount accounts and the day each was opened.
+    opened: BTreeMap<String, NaiveDate>,
+    current: Option<Block>,
+}
+
+impl Reader {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let trimmed = line.trim();
+        let ledger_comment = self.syntax == Syntax::Ledger && line.starts_with(['#', '*']);
+        if trimmed.is_empty() || trimmed.starts_with(';') || ledger_comment {
+            return Ok(());
+        }
+        if line.starts_with([' ', '\t']) {
+            return self.posting(trimmed);
+        }
+        self.finish()?;
+        match self.syntax {
+            Syntax::Ledger => self.ledger_header(trimmed),
+            Syntax::Beancount => self.beancount_header(trimmed),
+        }
+    }
+
+    fn ledger_header(&mut self, line: &str) -> Result<(), String> {
+        if let Some(account) = line.strip_prefix("account ") {
+            self.journal.accounts.push(strip_comment(account).to_string());
+            self.current = Some(Block::Other);
+        } else if let Some(period) = line.strip_prefix('~') {
+            let words: Vec<&str> = strip_comment(period).split_whitespace().collect();
+            let from = match words.as_slice() {
+                ["monthly", "from", from, ..] => NaiveDate::parse_from_str(from, "%Y-%m-%d")
+                    .map_err(|_| format!("\"{}\" is not a date", from))?,
+                _ => return Err(format!("unsupported period \"{}\"", period.trim())),
+            };
+            self.current = Some(Block::Periodic(Period::of(from), Vec::new()));
+        } else if line.starts_with(|c: char| c.is_ascii_digit()) {
+            let (date, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let date = date.split('=').next().unwrap_or_default();
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("\"{}\" is not a date", date))?;
+            let rest = rest.trim_start();
+            let rest = rest.strip_prefix(['*', '!']).unwrap_or(rest).trim_start();
+            let entry = Entry {
+                date,
+                payee: strip_comment(rest).to_string(),
+                postings: Vec::new(),
+            };
+            self.current = Some(Block::Entry(entry, Vec::new()));
+        } else {
+            // Other directives (commodity, include, …) carry nothing we read.
+            self.current = Some(Block::Other);
+        }
+        Ok(())
+    }
+
+    fn beancount_header(&mut self, line: &str) -> Result<(), String> {
+        if line.starts_with("option ") || line.starts_with("plugin ") || line.starts_with("include ") {
+            self.current = Some(Block::Other);
+            return Ok(());
+        }
+        let (date, rest) = line.split_once(char::is_whitespace).ok_or("unexpected line")?;
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("\"{}\" is not a date", date))?;
+        let rest = rest.trim_start();
+        let (keyword, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        self.current = Some(Block::Other);
+        match keyword {
+            "open" => {
+                let account = strip_comment(rest).split_whitespace().next().ok_or("open without an account")?;
+                self.opened.insert(account.to_string(), date);
+            }
+            "custom" => {
+                let words = tokens(rest)?;
+                if words.first().is_some_and(|(kind, quoted)| kind == "budget" && *quoted) {
+                    // Fava's form: the account bare and opened, the interval quoted.
+                    let [_, (account, false), (interval, true), (number, false), (currency, false)] = words.as_slice()
+                    else {
+                        return Err("a budget needs an account, an interval and an amount".to_string());
+                    };
+                    if interval != "monthly" {
+                        return Err(format!("unsupported budget interval \"{}\"", interval));
+                    }
+                    if self.opened.get(account).is_none_or(|opened| *opened > date) {
+                        return Err(format!("{} is used before it is opened", account));
+                    }
+                    self.journal.budgets.push(Budget {
+                        from: Period::of(date),
+                        account: account.clone(),
+                        amount: amount(number, currency)?,
+                    });
+                }
+            }
+            "*" | "!" | "txn" => {
+                // Payee and narration are quoted; tags and links are not.
+                let payee = tokens(rest)?
+                    .into_iter()
+                    .find(|(word, quoted)| *quoted && !word.is_empty())
+                    .map(|(word, _)| word)
+                    .unwrap_or_default();
+                let entry = Entry {
+                    date,
+                    payee,
+                    postings: Vec::new(),
+                };
+                self.current = Some(Block::Entry(entry, Vec::new()));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn posting(&mut self, line: &str) -> Result<(), String> {
+        let line = strip_comment(line);
+        let postings = match &mut self.current {
+            Some(Block::Entry(_, postings) | Block::Periodic(_, postings)) => postings,
+            Some(Block::Other) => return Ok(()),
+            None => return Err("indented line outside an entry".to_string()),
+        };
+        // Beancount metadata such as `id: "…"`.
+        if self.syntax == Syntax::Beancount && line.starts_with(|c: char| c.is_ascii_lowercase()) {
+            return Ok(());
+        }
+        let line = line.strip_prefix(['*', '!']).unwrap_or(line).trim_start();
+        let (account, amount_text) = match self.syntax {
+            // Ledger account names may hold single spaces; two end them.
+            Syntax::Ledger => line.split_once("  ").or_else(|| line.split_once('\t')).unwrap_or((line, "")),
+            Syntax::Beancount => line.split_once(char::is_whitespace).unwrap_or((line, "")),
+        };
+        let amount_text = amount_text.trim();
+        let money = if amount_text.is_empty() {
+            None
+        } else {
+            let words: Vec<&str> = amount_text.split_whitespace().collect();
+            match words.as_slice() {
+                [number, currency] if currency.chars().all(|c| c.is_ascii_alphabetic()) => {
+                    Some(amount(number, currency)?)
+                }
+                [currency, number] => Some(amount(number, currency)?),
+                _ => return Err(format!("cannot read the amount \"{}\"", amount_text)),
+            }
+        };
+        postings.push((account.trim().to_string(), money));
+        Ok(())
+    }
+
+    /// Closes the block being read, filling in an elided amount and
+    /// checking that it balances.
+    fn finish(&mut self) -> Result<(), String> {
+        match self.current.take() {
+            Some(Block::Entry(mut entry, postings)) => {
+                entry.postings =
+                    balance(postings).map_err(|err| format!("{} on {}: {}", entry.payee, entry.date, err))?;
+                if self.syntax == Syntax::Beancount {
+                    for posting in &entry.postings {
+                        match self.opened.get(&posting.account) {
+                            Some(opened) if *opened <= entry.date => {}
+                            _ => return Err(format!("{} is used before it is opened", posting.account)),
+                        }
+                    }
+                }
+                self.journal.entries.push(entry);
+            }
+            Some(Block::Periodic(from, postings)) => {
+                let (account, amount) = postings
+                    .into_iter()
+                    .find_map(|(account, amount)| Some((account, amount?)))
+                    .ok_or("periodic transaction without an amount")?;
+                self.journal.budgets.push(Budget { from, account, amount });
+            }
+            Some(Block::Other) | None => {}
+        }
+        Ok(())
+    }
+}
+
+/// The postings with at most one missing amount filled in, or an error if
+/// they do not add up to zero in every currency.
+fn balance(postings: Vec<(String, Option<Money>)>) -> Result<Vec<Posting>, String> {
+    let mut totals: BTreeMap<Currency, i64> = BTreeMap::new();
+    for amount in postings.iter().filter_map(|(_, amount)| *amount) {
+        *totals.entry(amount.currency()).or_default() += amount.cents();
+    }
+    let missing = postings.iter().filter(|(_, amount)| amount.is_none()).count();
+    let open: Vec<(Currency, i64)> = totals.into_iter().filter(|(_, cents)| *cents != 0).collect();
+    let fill = match (missing, open.as_slice()) {
+        (0, []) => None,
+        (1, [(currency, cents)]) => Some(Money::from_cents(-cents, *currency)),
+        (1, []) => Some(Money::default()),
+        (0, _) => return Err("the postings do not balance".to_string()),
+        _ => return Err("only one posting may leave out its amount".to_string()),
+    };
+    Ok(postings
+        .into_iter()
+        .map(|(account, amount)| Posting {
+            account,
+            amount: amount.or(fill).unwrap_or_default(),
+        })
+        .collect())
+}
+
+fn amount(number: &str, currency: &str) -> Result<Money, String> {
+    let currency: Currency = currency.parse()?;
+    Money::parse(number, currency)
+}
+
+/// Cuts a trailing `; comment` off a line.
+fn strip_comment(line: &str) -> &str {
+    line.split_once(';').map_or(line, |(before, _)| before).trim_end()
+}
+
+/// Splits beancount's space-separated words up to a `;` comment, reading
+/// `"quoted strings"` (with `\"` escapes) as single words marked `true`.
+fn tokens(text: &str) -> Result<Vec<(String, bool)>, String> {
+    let mut words = Vec::new();
+    let mut chars = text.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::new();
+            loop {
+                match chars.next() {
+                    Some('\\') => word.extend(chars.next()),
+                    Some('"') => break,
+                    Some(c) => word.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            words.push((word, true));
+        } else {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+            words.push((word, false));
+        }
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema;
+
+    fn fixture() -> PersistedState {
+        schema::load_fixture(include_str!("../tests/fixtures/budget_data_v2.json")).0
+    }
+
+    #[test]
+    fn exports_read_back_in_both_syntaxes() {
+        let mut state = fixture();
+        state.period_budgets.clear();
+        let currency = state.monthly_budget.currency();
+        let first = Period::of(state.transactions.iter().map(|t| t.date).min().unwrap());
+        state.period_budgets.insert(first.next(), Money::from_cents(1_234_500, currency));
+        state.transactions[0].title = "Say \"hi\";  twice".to_string();
+
+        for syntax in [Syntax::Ledger, Syntax::Beancount] {
+            let text = export(&state, syntax).unwrap();
+            let journal = parse(&text, syntax).unwrap();
+            let openings = state.accounts.iter().filter(|a| !a.opening_balance.is_zero()).count();
+            assert_eq!(journal.entries.len(), state.transactions.len() + openings);
+            let amounts: Vec<Money> = journal.budgets.iter().map(|b| b.amount).collect();
+            if syntax == Syntax::Beancount {
+                assert!(text.contains(" custom \"budget\" Expenses:Budget \"monthly\" "));
+                assert!(journal.accounts.iter().any(|a| a == BEANCOUNT_BUDGET_ACCOUNT));
+            }
+            assert_eq!(amounts, [state.monthly_budget, Money::from_cents(1_234_500, currency), state.monthly_budget]);
+        }
+    }
+
+    #[test]
+    fn rejects_unbalanced_entries() {
+        let ledger = "2024-01-05 * Market\n    Expenses:Food  10.00 PHP\n    Assets:Cash  -9.00 PHP\n";
+        assert!(parse(ledger, Syntax::Ledger).unwrap_err().contains("do not balance"));
+        let elided = "2024-01-05 Market ; weekly\n    Expenses:Food  10.00 PHP\n    Assets:Cash Wallet\n";
+        let journal = parse(elided, Syntax::Ledger).unwrap();
+        assert_eq!(journal.entries[0].postings[1].amount, Money::from_cents(-1000, Currency::PHP));
+        let beancount = "2024-01-05 * \"Market\"\n  Expenses:Food  10.00 PHP\n  Assets:Cash  -10.00 PHP\n";
+        assert!(parse(beancount, Syntax::Beancount).unwrap_err().contains("before it is opened"));
+        let quoted = "2024-01-01 open Expenses:Budget\n\
+            2024-01-01 custom \"budget\" \"Expenses:Budget\" \"monthly\" 5 PHP\n";
+        assert!(parse(quoted, Syntax::Beancount).unwrap_err().contains("needs an account"));
+        let unopened = "2024-01-01 custom \"budget\" Expenses:Budget \"monthly\" 5 PHP\n";
+        assert!(parse(unopened, Syntax::Beancount).unwrap_err().contains("before it is opened"));
+    }
+}
@@ -1,11 +1,39 @@
 #![cfg_attr(target_os = "windows", windows_subsystem = "windows")]
 
+mod cli;
+mod crypto;
+mod csv_export;
+mod csv_import;
+mod date;
+mod history;
+mod journal;
+mod merge;
+mod model;
+mod money;
+mod ofx;
+mod paths;
+mod qif;
+mod recurring;
+mod schema;
+mod settings;
+mod sqlite_store;
+mod storage;
+
+use chrono::NaiveDate;
 use eframe::egui;
 use egui::{Color32, RichText};
-use serde::{Deserialize, Serialize};
-use std::fs;
+use history::{Command, History, Touched};
+use merge::{Merge, Side};
+use date::Period;
+use money::{Currency, Money};
+use model::{Account, AccountKind, BudgetData, Category, PersistedState, Split, Transaction};
+use recurring::{Frequency, RecurringRule};
+use settings::{Profile, Settings};
 use std::path::PathBuf;
+use storage::Store;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use uuid::Uuid;
 
 fn main() -> eframe::Result<()> {
     let viewport = if let Some(icon) = app_icon() {
@@ -17,113 +45,1208 @@ fn main() -> eframe::Result<()> {
         viewport,
         ..Default::default()
     };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+    let data_file = paths::data_file_arg(args);
     eframe::run_native(
         "DYBudget",
         options,
-        Box::new(|_cc| Box::new(BudgetApp::default())),
+        Box::new(|_cc| Box::new(BudgetApp::new(data_file))),
     )
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Transaction {
-    title: String,
-    date: String,
-    amount: f64,
-    color: Color32,
+/// How often the data file is checked for changes made by other programs.
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const ACCOUNT_ACCENTS: [Color32; 4] = [
+    Color32::from_rgb(120, 255, 205),
+    Color32::from_rgb(255, 196, 110),
+    Color32::from_rgb(110, 200, 255),
+    Color32::from_rgb(255, 130, 170),
+];
+
+const DEFAULT_CATEGORY_COLOR: Color32 = Color32::from_rgb(88, 172, 255);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Home,
+    Accounts,
+    Categories,
+    Recurring,
+    Expenses,
+    Income,
+    Options,
+}
+
+/// Shown instead of the pages when the data file exists but could not be
+/// read. Nothing is saved until the user picks a way out.
+#[derive(Debug)]
+struct Recovery {
+    error: String,
+    /// Where the unreadable file was copied, or why copying failed.
+    corrupt_copy: Result<PathBuf, String>,
+    backup_path: String,
+}
+
+/// Shown instead of the pages while an encrypted budget waits for its
+/// passphrase. Nothing is saved until it is unlocked.
+#[derive(Debug, Default)]
+struct Unlock {
+    passphrase: String,
+    /// Why the last attempt failed, usually a wrong passphrase.
+    error: Option<String>,
+}
+
+/// The "New budget" and "Open budget…" dialog.
+#[derive(Debug)]
+struct BudgetDialog {
+    /// True for "New budget", false for "Open budget…".
+    create: bool,
+    name: String,
+    path: String,
+}
+
+/// The "Merge budgets…" window, also used to settle conflicts when merging
+/// an outside change to the open file.
+#[derive(Debug, Default)]
+struct MergeDialog {
+    /// The copy both versions started from.
+    base: String,
+    /// The other person's copy.
+    theirs: String,
+    merge: Option<Merge>,
+    /// True when "theirs" is the open file as changed on disk.
+    from_disk: bool,
+}
+
+/// The CSV import wizard: pick a file, then map its columns.
+#[derive(Debug)]
+struct CsvImportDialog {
+    path: String,
+    delimiter: u8,
+    has_header: bool,
+    /// The file as last read with `delimiter` and `has_header`.
+    table: Option<csv_import::Table>,
+    date: usize,
+    description: usize,
+    /// Used when `debit_credit` is off.
+    amount: usize,
+    debit_credit: bool,
+    debit: usize,
+    credit: usize,
+    date_format: String,
+    decimal_comma: bool,
+    account: Uuid,
+}
+
+impl CsvImportDialog {
+    fn mapping(&self) -> csv_import::Mapping {
+        csv_import::Mapping {
+            date: self.date,
+            description: self.description,
+            amount: if self.debit_credit {
+                csv_import::AmountColumns::DebitCredit {
+                    debit: self.debit,
+                    credit: self.credit,
+                }
+            } else {
+                csv_import::AmountColumns::Signed(self.amount)
+            },
+            date_format: self.date_format.clone(),
+            decimal_comma: self.decimal_comma,
+        }
+    }
+
+    /// Reads the file again, guessing the delimiter the first time and the
+    /// columns whenever the shape of the table changes.
+    fn load(&mut self, guess_delimiter: bool) -> Result<(), String> {
+        let path = PathBuf::from(self.path.trim());
+        if guess_delimiter {
+            let text = std::fs::read_to_string(&path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+            self.delimiter = csv_import::guess_delimiter(&text);
+        }
+        let table = csv_import::read(&path, self.delimiter, self.has_header)?;
+        let (date, description, amount) = csv_import::guess_columns(&table.headers);
+        self.date = date;
+        self.description = description;
+        match amount {
+            csv_import::AmountColumns::Signed(column) => {
+                self.debit_credit = false;
+                self.amount = column;
+            }
+            csv_import::AmountColumns::DebitCredit { debit, credit } => {
+                self.debit_credit = true;
+                self.debit = debit;
+                self.credit = credit;
+            }
+        }
+        if let Some(format) = csv_import::guess_date_format(&table, date) {
+            self.date_format = format.to_string();
+        }
+        self.table = Some(table);
+        Ok(())
+    }
+}
+
+/// Importing an OFX/QFX statement into one account.
+#[derive(Debug)]
+struct OfxImportDialog {
+    path: String,
+    statement: Option<ofx::Statement>,
+    account: Uuid,
+}
+
+/// Importing a QIF file; registers without an account header go into `account`.
+#[derive(Debug)]
+struct QifImportDialog {
+    path: String,
+    day_first: bool,
+    file: Option<qif::QifFile>,
+    account: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Qif,
+    Csv,
+    Ledger,
+    Beancount,
+}
+
+impl ExportFormat {
+    fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Qif => "QIF",
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Ledger => "ledger journal",
+            ExportFormat::Beancount => "beancount",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Qif => "qif",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ledger => "journal",
+            ExportFormat::Beancount => "beancount",
+        }
+    }
+}
+
+/// Which transactions a CSV export writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportScope {
+    All,
+    Period,
+    /// The selected period narrowed by the transaction list's filter.
+    Filter,
+}
+
+/// Writing the budget to another program's format.
+#[derive(Debug)]
+struct ExportDialog {
+    format: ExportFormat,
+    path: String,
+    /// Used by CSV exports only.
+    scope: ExportScope,
+    csv: csv_export::Options,
+}
+
+/// Narrows the transaction list on the home page.
+#[derive(Debug, Clone, Default)]
+struct TxFilter {
+    /// Matched against titles, ignoring case.
+    search: String,
+    account: Option<Uuid>,
+    category: Option<Uuid>,
+}
+
+impl TxFilter {
+    fn is_active(&self) -> bool {
+        !self.search.trim().is_empty() || self.account.is_some() || self.category.is_some()
+    }
+
+    fn matches(&self, tx: &Transaction) -> bool {
+        let search = self.search.trim().to_lowercase();
+        (search.is_empty() || tx.title.to_lowercase().contains(&search))
+            && self.account.is_none_or(|a| tx.account == a || tx.transfer_to == Some(a))
+            && self.category.is_none_or(|c| tx.uses_category(c))
+    }
+}
+
+enum DiskAction {
+    Reload,
+    Merge,
+    Overwrite,
+}
+
+enum RecoveryAction {
+    Backup(PathBuf),
+    Partial,
+    Fresh,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct PersistedState {
-    monthly_budget: f64,
-    transactions: Vec<Transaction>,
+/// A split line as typed into the transaction form.
+#[derive(Debug, Clone, Default)]
+struct SplitForm {
+    category: Option<Uuid>,
+    amount: String,
+    memo: String,
 }
 
 #[derive(Debug)]
 struct BudgetApp {
-    monthly_budget: f64,
+    data: BudgetData,
+    period: Period,
+    tx_filter: TxFilter,
     budget_input: String,
     entry_description: String,
     entry_amount: String,
-    transactions: Vec<Transaction>,
+    entry_account: Uuid,
+    entry_category: Option<Uuid>,
     status: String,
+    page: Page,
     show_new_tx: bool,
     form_title: String,
     form_amount: String,
     form_date: String,
+    form_account: Uuid,
+    form_category: Option<Uuid>,
+    form_splits: Vec<SplitForm>,
+    form_transfer: bool,
+    form_transfer_to: Uuid,
+    editing_tx: Option<Uuid>,
+    history: History,
+    show_history: bool,
+    confirm_delete_tx: Option<Uuid>,
+    account_form_name: String,
+    account_form_kind: AccountKind,
+    account_form_opening: String,
+    editing_account: Option<Uuid>,
+    category_form_name: String,
+    category_form_color: Color32,
+    category_form_icon: String,
+    category_form_parent: Option<Uuid>,
+    editing_category: Option<Uuid>,
+    merge_source: Option<Uuid>,
+    merge_target: Option<Uuid>,
+    show_archived_categories: bool,
+    rule_form_title: String,
+    rule_form_amount: String,
+    rule_form_account: Uuid,
+    rule_form_category: Option<Uuid>,
+    rule_form_frequency: Frequency,
+    rule_form_start: String,
+    rule_form_end: String,
+    editing_rule: Option<Uuid>,
+    recovery: Option<Recovery>,
+    settings: Settings,
+    /// Why the last save failed; cleared by the next successful one.
+    save_error: Option<String>,
+    store: Box<dyn Store>,
+    data_path: PathBuf,
+    /// True when `--data-file` chose the file, which beats the setting.
+    data_path_from_args: bool,
+    data_file_input: String,
+    budget_dialog: Option<BudgetDialog>,
+    unlock: Option<Unlock>,
+    merge_dialog: Option<MergeDialog>,
+    csv_import: Option<CsvImportDialog>,
+    ofx_import: Option<OfxImportDialog>,
+    qif_import: Option<QifImportDialog>,
+    export: Option<ExportDialog>,
+    /// The budget as last read from or written to disk, so changes made here
+    /// can be merged into a copy someone else wrote.
+    synced: Option<PersistedState>,
+    /// What the data file looked like after our last read or write.
+    disk_stamp: Option<(SystemTime, u64)>,
+    last_disk_check: Instant,
+    /// Set when another program changed the data file; saving waits until
+    /// the user reloads, merges or overwrites it.
+    disk_changed: bool,
+    passphrase_input: String,
+    passphrase_confirm: String,
 }
 
-impl Default for BudgetApp {
-    fn default() -> Self {
+impl BudgetApp {
+    /// Opens the budget at `data_file` if given, otherwise the one chosen in
+    /// the settings or the per-user default location.
+    fn new(data_file: Option<PathBuf>) -> Self {
+        let settings = Settings::load(&Settings::path());
+        let data_path_from_args = data_file.is_some();
+        let mut status = String::new();
+        let data_path = match data_file.or_else(|| settings.data_file.clone()) {
+            Some(path) => path,
+            None => {
+                let path = paths::default_data_file();
+                match paths::migrate_working_dir_file(&path) {
+                    Ok(Some(old)) => {
+                        status = format!("Moved {} to {}.", old.display(), path.display());
+                        path
+                    }
+                    Ok(None) => path,
+                    Err(err) => {
+                        // Keep using the old file rather than starting an empty one.
+                        status = err;
+                        PathBuf::from(paths::DATA_FILE_NAME)
+                    }
+                }
+            }
+        };
+        let data_file_input = settings
+            .data_file
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
         let mut app = Self {
-            monthly_budget: 2500.0,
-            budget_input: "2500".to_string(),
+            data: BudgetData::default(),
+            period: Period::current(),
+            tx_filter: TxFilter::default(),
+            budget_input: String::new(),
             entry_description: String::new(),
             entry_amount: String::new(),
-            transactions: vec![],
-            status: String::new(),
+            entry_account: Uuid::nil(),
+            entry_category: None,
+            status,
+            page: Page::Home,
             show_new_tx: false,
             form_title: String::new(),
             form_amount: String::new(),
             form_date: "Today".to_string(),
+            form_account: Uuid::nil(),
+            form_category: None,
+            form_splits: Vec::new(),
+            form_transfer: false,
+            form_transfer_to: Uuid::nil(),
+            editing_tx: None,
+            history: History::default(),
+            show_history: false,
+            confirm_delete_tx: None,
+            account_form_name: String::new(),
+            account_form_kind: AccountKind::Cash,
+            account_form_opening: String::new(),
+            editing_account: None,
+            category_form_name: String::new(),
+            category_form_color: DEFAULT_CATEGORY_COLOR,
+            category_form_icon: String::new(),
+            category_form_parent: None,
+            editing_category: None,
+            merge_source: None,
+            merge_target: None,
+            show_archived_categories: false,
+            rule_form_title: String::new(),
+            rule_form_amount: String::new(),
+            rule_form_account: Uuid::nil(),
+            rule_form_category: None,
+            rule_form_frequency: Frequency::Monthly,
+            rule_form_start: "Today".to_string(),
+            rule_form_end: String::new(),
+            editing_rule: None,
+            recovery: None,
+            settings,
+            save_error: None,
+            store: storage::open(&data_path, 0),
+            data_path: data_path.clone(),
+            data_path_from_args,
+            data_file_input,
+            budget_dialog: None,
+            unlock: None,
+            merge_dialog: None,
+            csv_import: None,
+            ofx_import: None,
+            qif_import: None,
+            export: None,
+            synced: None,
+            disk_stamp: None,
+            last_disk_check: Instant::now(),
+            disk_changed: false,
+            passphrase_input: String::new(),
+            passphrase_confirm: String::new(),
         };
-        if let Some(saved) = app.load_state() {
-            app.monthly_budget = saved.monthly_budget;
-            app.budget_input = format!("{:.2}", saved.monthly_budget);
-            app.transactions = saved.transactions;
-        } else {
-            // Seed a few to match the mock.
-            app.transactions = vec![
-                Transaction {
-                    title: "teva overflow".to_string(),
-                    date: "Aug 31, 2023".to_string(),
-                    amount: -458.00,
-                    color: Color32::from_rgb(42, 201, 121),
-                },
-                Transaction {
-                    title: "mcdonald".to_string(),
-                    date: "Aug 30, 2023".to_string(),
-                    amount: -119.46,
-                    color: Color32::from_rgb(230, 78, 95),
-                },
-                Transaction {
-                    title: "bath and bodyworks".to_string(),
-                    date: "Aug 30, 2023".to_string(),
-                    amount: -80.00,
-                    color: Color32::from_rgb(110, 133, 255),
-                },
-                Transaction {
-                    title: "dominos pizza".to_string(),
-                    date: "Aug 28, 2023".to_string(),
-                    amount: -81.00,
-                    color: Color32::from_rgb(230, 156, 71),
-                },
-                Transaction {
-                    title: "dr.locker".to_string(),
-                    date: "Aug 28, 2023".to_string(),
-                    amount: -40.00,
-                    color: Color32::from_rgb(180, 180, 200),
-                },
-            ];
-        }
+        app.load_budget(data_path, true);
         app
     }
 }
 
 impl BudgetApp {
-    fn total_spent(&self) -> f64 {
-        self.transactions.iter().map(|t| t.amount.abs()).sum()
+    /// Replaces the open budget with the one in `path`. A missing file gets
+    /// the sample budget when `seed_if_missing` is set (first launch) and an
+    /// empty one otherwise; an unreadable one goes to the recovery screen.
+    fn load_budget(&mut self, path: PathBuf, seed_if_missing: bool) {
+        self.store = storage::open(&path, self.settings.backup_retention);
+        self.data_path = path;
+        self.data = BudgetData::default();
+        self.history = History::default();
+        self.recovery = None;
+        self.unlock = None;
+        self.merge_dialog = None;
+        self.csv_import = None;
+        self.ofx_import = None;
+        self.qif_import = None;
+        self.export = None;
+        self.tx_filter = TxFilter::default();
+        self.disk_changed = false;
+        self.synced = None;
+        self.save_error = None;
+        self.show_new_tx = false;
+        self.editing_tx = None;
+        self.confirm_delete_tx = None;
+        self.merge_source = None;
+        self.clear_account_form();
+        self.clear_category_form();
+        self.clear_rule_form();
+        if storage::is_encrypted(&self.data_path) {
+            // Wait for the passphrase; the placeholder account is never saved.
+            self.data.accounts.push(Account::new("Cash", AccountKind::Cash, Money::default()));
+            self.unlock = Some(Unlock::default());
+            self.reset_selection();
+            return;
+        }
+        let loaded = self.store.load();
+        self.apply_loaded(loaded, seed_if_missing);
+    }
+
+    /// Takes over what the store loaded, or enters recovery if it failed.
+    fn apply_loaded(&mut self, loaded: Result<Option<(PersistedState, bool)>, String>, seed_if_missing: bool) {
+        let mut unreadable = Vec::new();
+        match loaded {
+            Ok(Some((mut saved, mut migrated))) => {
+                migrated |= saved.normalize();
+                unreadable = std::mem::take(&mut saved.unreadable_dates);
+                self.data = saved.into();
+                if migrated {
+                    // Persist the upgraded file once so it is only migrated on first load.
+                    self.save();
+                }
+            }
+            Err(error) => {
+                // Keep the broken file untouched and wait for the user to decide.
+                self.data.accounts.push(Account::new("Cash", AccountKind::Cash, Money::default()));
+                self.recovery = Some(Recovery {
+                    error,
+                    corrupt_copy: storage::preserve_corrupt(&self.data_path),
+                    backup_path: String::new(),
+                });
+            }
+            Ok(None) if seed_if_missing => self.seed(),
+            Ok(None) => {
+                let mut state = PersistedState::from(&self.data);
+                state.normalize();
+                self.data = state.into();
+                self.save();
+            }
+        }
+        self.reset_selection();
+        if self.recovery.is_none() {
+            self.synced = Some(PersistedState::from(&self.data));
+            self.disk_stamp = storage::stamp(&self.data_path);
+            self.catch_up_on_launch();
+        }
+        if !unreadable.is_empty() {
+            self.status = self.unreadable_dates_notice(&unreadable);
+        }
+    }
+
+    /// Names the transactions whose old free-text date had to be guessed.
+    fn unreadable_dates_notice(&self, ids: &[Uuid]) -> String {
+        let titles: Vec<String> = self
+            .data
+            .transactions
+            .iter()
+            .filter(|t| ids.contains(&t.id))
+            .map(|t| format!("\"{}\"", t.title))
+            .collect();
+        format!(
+            "Could not read the old date of {} transaction(s), so they are dated the day the file was last saved: {}.",
+            titles.len(),
+            titles.join(", ")
+        )
+    }
+
+    /// Points the period and form pickers at the freshly loaded budget.
+    fn reset_selection(&mut self) {
+        self.select_period(Period::current());
+        let first = self.data.accounts[0].id;
+        self.entry_account = first;
+        self.form_account = first;
+        self.form_transfer_to = first;
+        self.rule_form_account = first;
+        self.entry_category = None;
+        self.form_category = None;
+    }
+
+    fn unlock_budget(&mut self) {
+        let Some(unlock) = &mut self.unlock else {
+            return;
+        };
+        let passphrase = std::mem::take(&mut unlock.passphrase);
+        match self.store.unlock(&passphrase) {
+            // No key means the passphrase itself was rejected; stay locked.
+            Err(err) if self.store.key().is_none() => unlock.error = Some(err),
+            loaded => {
+                self.unlock = None;
+                self.data = BudgetData::default();
+                self.status.clear();
+                self.apply_loaded(loaded, false);
+                if self.recovery.is_none() {
+                    let unlocked = format!("Unlocked {}.", self.budget_name_for(&self.data_path));
+                    self.status = format!("{} {}", unlocked, self.status).trim_end().to_string();
+                }
+            }
+        }
+    }
+
+    fn unlock_screen(&mut self, ui: &mut egui::Ui) {
+        let name = self.budget_name_for(&self.data_path);
+        let Some(unlock) = &mut self.unlock else {
+            return;
+        };
+        let mut submit = false;
+        ui.label(
+            RichText::new(format!("{} is locked", name))
+                .color(Color32::from_rgb(230, 232, 240))
+                .size(20.0)
+                .strong(),
+        );
+        ui.add_space(10.0);
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                ui.label(format!("Enter the passphrase for {}.", self.data_path.display()));
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    let field = ui.add(
+                        egui::TextEdit::singleline(&mut unlock.passphrase)
+                            .password(true)
+                            .hint_text("Passphrase")
+                            .desired_width(260.0),
+                    );
+                    let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    submit = ui.button("Unlock").clicked() || entered;
+                });
+                if let Some(error) = &unlock.error {
+                    ui.add_space(6.0);
+                    ui.label(RichText::new(error).color(Color32::from_rgb(235, 120, 120)));
+                }
+            });
+        let others: Vec<PathBuf> = self
+            .settings
+            .recent_files
+            .iter()
+            .filter(|p| **p != self.data_path)
+            .cloned()
+            .collect();
+        let mut open = None;
+        if !others.is_empty() {
+            ui.add_space(12.0);
+            ui.label(RichText::new("Open another budget").strong());
+            for path in others {
+                if ui.button(self.budget_name_for(&path)).on_hover_text(path.display().to_string()).clicked() {
+                    open = Some(path);
+                }
+            }
+        }
+        if submit && self.unlock.as_ref().is_some_and(|u| !u.passphrase.is_empty()) {
+            self.unlock_budget();
+        }
+        if let Some(path) = open {
+            self.open_budget(path);
+        }
+    }
+
+    /// Switches to the budget in `path`, remembering it for the next launch
+    /// and in the recent files.
+    fn open_budget(&mut self, path: PathBuf) {
+        if self.save_error.is_some() || self.disk_changed {
+            self.status = "The current budget has unsaved changes; save or reload it before switching.".to_string();
+            return;
+        }
+        if path == self.data_path {
+            return;
+        }
+        let previous = self.data_path.clone();
+        self.settings.remember(&previous);
+        self.settings.remember(&path);
+        self.settings.data_file = Some(path.clone());
+        self.data_file_input = path.display().to_string();
+        self.status = match self.settings.save(&Settings::path()) {
+            Ok(()) => format!("Opened {}.", self.budget_name_for(&path)),
+            Err(err) => err,
+        };
+        self.load_budget(path, false);
+    }
+
+    /// Profile name for `path`, or its file name for budgets without a profile.
+    fn budget_name_for(&self, path: &std::path::Path) -> String {
+        match self.settings.profile_for(path) {
+            Some(profile) => profile.name.clone(),
+            None => path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("Budget")
+                .to_string(),
+        }
+    }
+
+    /// Handles the New budget / Open budget dialog's confirm button.
+    fn confirm_budget_dialog(&mut self) {
+        let Some(dialog) = &self.budget_dialog else {
+            return;
+        };
+        let name = dialog.name.trim().to_string();
+        if dialog.create && name.is_empty() {
+            self.status = "Give the new budget a name.".to_string();
+            return;
+        }
+        let path = if dialog.path.trim().is_empty() {
+            if !dialog.create {
+                self.status = "Enter the path of the budget file to open.".to_string();
+                return;
+            }
+            Self::new_budget_path(&name)
+        } else {
+            PathBuf::from(dialog.path.trim())
+        };
+        if dialog.create && path.exists() {
+            self.status = format!("{} already exists; use Open budget… instead.", path.display());
+            return;
+        }
+        if !dialog.create && !path.is_file() {
+            self.status = format!("{} does not exist.", path.display());
+            return;
+        }
+        if !name.is_empty() {
+            self.settings.profiles.retain(|p| p.data_file != path);
+            self.settings.profiles.push(Profile {
+                name,
+                data_file: path.clone(),
+            });
+        }
+        self.budget_dialog = None;
+        self.open_budget(path);
+    }
+
+    /// A file in the data folder named after `name` that does not exist yet.
+    fn new_budget_path(name: &str) -> PathBuf {
+        let slug: String = name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let slug = slug.trim_matches('-');
+        let slug = if slug.is_empty() { "budget" } else { slug };
+        let dir = paths::data_dir();
+        (1..)
+            .map(|n| match n {
+                1 => dir.join(format!("{}.json", slug)),
+                n => dir.join(format!("{}-{}.json", slug, n)),
+            })
+            .find(|path| !path.exists())
+            .unwrap_or_else(|| dir.join(paths::DATA_FILE_NAME))
+    }
+
+    /// Sidebar header menu: profiles, recent files, New budget and Open budget.
+    fn budget_switcher(&mut self, ui: &mut egui::Ui) {
+        let mut open = None;
+        let current = self.budget_name_for(&self.data_path);
+        ui.menu_button(format!("{} ▾", current), |ui| {
+            ui.set_min_width(200.0);
+            if self.settings.profiles.is_empty() {
+                ui.label(RichText::new("No profiles yet").color(Color32::from_rgb(150, 155, 165)));
+            }
+            for profile in &self.settings.profiles {
+                let selected = profile.data_file == self.data_path;
+                if ui
+                    .selectable_label(selected, &profile.name)
+                    .on_hover_text(profile.data_file.display().to_string())
+                    .clicked()
+                {
+                    open = Some(profile.data_file.clone());
+                    ui.close_menu();
+                }
+            }
+            ui.separator();
+            ui.menu_button("Recent files", |ui| {
+                let recent: Vec<&PathBuf> = self
+                    .settings
+                    .recent_files
+                    .iter()
+                    .filter(|p| **p != self.data_path)
+                    .collect();
+                if recent.is_empty() {
+                    ui.label(RichText::new("Nothing yet").color(Color32::from_rgb(150, 155, 165)));
+                }
+                for path in recent {
+                    if ui.button(path.display().to_string()).clicked() {
+                        open = Some(path.clone());
+                        ui.close_menu();
+                    }
+                }
+            });
+            ui.separator();
+            if ui.button("New budget").clicked() {
+                self.budget_dialog = Some(BudgetDialog {
+                    create: true,
+                    name: String::new(),
+                    path: String::new(),
+                });
+                ui.close_menu();
+            }
+            if ui.button("Open budget…").clicked() {
+                self.budget_dialog = Some(BudgetDialog {
+                    create: false,
+                    name: String::new(),
+                    path: String::new(),
+                });
+                ui.close_menu();
+            }
+            if ui.button("Merge budgets…").clicked() {
+                self.merge_dialog = Some(MergeDialog::default());
+                ui.close_menu();
+            }
+        });
+        if let Some(path) = open {
+            self.open_budget(path);
+        }
+    }
+
+    /// Sidebar File menu: importing from and exporting to other programs.
+    fn file_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("File ▾", |ui| {
+            ui.set_min_width(180.0);
+            if ui.button("Import CSV…").clicked() {
+                self.csv_import = Some(CsvImportDialog {
+                    path: String::new(),
+                    delimiter: b',',
+                    has_header: true,
+                    table: None,
+                    date: 0,
+                    description: 1,
+                    amount: 2,
+                    debit_credit: false,
+                    debit: 2,
+                    credit: 3,
+                    date_format: csv_import::DATE_FORMATS[0].to_string(),
+                    decimal_comma: false,
+                    account: self.entry_account,
+                });
+                ui.close_menu();
+            }
+            if ui.button("Import OFX/QFX…").clicked() {
+                self.ofx_import = Some(OfxImportDialog {
+                    path: String::new(),
+                    statement: None,
+                    account: self.entry_account,
+                });
+                ui.close_menu();
+            }
+            if ui.button("Import QIF…").clicked() {
+                self.qif_import = Some(QifImportDialog {
+                    path: String::new(),
+                    day_first: false,
+                    file: None,
+                    account: self.entry_account,
+                });
+                ui.close_menu();
+            }
+            ui.separator();
+            if ui.button("Export CSV…").clicked() {
+                self.open_export(ExportFormat::Csv);
+                ui.close_menu();
+            }
+            if ui.button("Export QIF…").clicked() {
+                self.open_export(ExportFormat::Qif);
+                ui.close_menu();
+            }
+            if ui.button("Export ledger journal…").clicked() {
+                self.open_export(ExportFormat::Ledger);
+                ui.close_menu();
+            }
+            if ui.button("Export beancount…").clicked() {
+                self.open_export(ExportFormat::Beancount);
+                ui.close_menu();
+            }
+        });
+    }
+
+    fn open_export(&mut self, format: ExportFormat) {
+        self.export = Some(ExportDialog {
+            format,
+            path: self.data_path.with_extension(format.extension()).display().to_string(),
+            scope: if self.tx_filter.is_active() { ExportScope::Filter } else { ExportScope::Period },
+            csv: csv_export::Options::default(),
+        });
+    }
+
+    fn budget_dialog_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.budget_dialog else {
+            return;
+        };
+        let mut open = true;
+        let mut confirm = false;
+        let mut cancel = false;
+        egui::Window::new(if dialog.create { "New budget" } else { "Open budget" })
+            .id(egui::Id::new("budget_dialog"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.set_width(380.0);
+                ui.label(if dialog.create { "Profile name" } else { "Profile name (optional)" });
+                ui.add(egui::TextEdit::singleline(&mut dialog.name).hint_text("Personal, Joint, Business…"));
+                ui.label(if dialog.create { "File (optional)" } else { "File" });
+                let hint = if dialog.create {
+                    "Defaults to the data folder".to_string()
+                } else {
+                    paths::data_dir().join("budget.json").display().to_string()
+                };
+                ui.add(egui::TextEdit::singleline(&mut dialog.path).hint_text(hint).desired_width(360.0));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    let label = if dialog.create { "Create" } else { "Open" };
+                    if ui
+                        .add(
+                            egui::Button::new(RichText::new(label).color(Color32::WHITE))
+                                .fill(Color32::from_rgb(92, 106, 255)),
+                        )
+                        .clicked()
+                    {
+                        confirm = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+        if !open || cancel {
+            self.budget_dialog = None;
+        } else if confirm {
+            self.confirm_budget_dialog();
+        }
+    }
+
+    /// Sample data for a first launch without a data file, to match the mock.
+    fn seed(&mut self) {
+        self.data.budget.default_amount = Money::from_cents(250000, Currency::PHP);
+        let cash = Account::new("Cash", AccountKind::Cash, Money::from_cents(100000, Currency::PHP));
+        let bank = Account::new("Bank", AccountKind::Bank, Money::from_cents(500000, Currency::PHP));
+        let shopping = Category::new("Shopping", Color32::from_rgb(42, 201, 121), "🛍", None);
+        let food = Category::new("Food", Color32::from_rgb(230, 78, 95), "🍔", None);
+        let takeout = Category::new("Takeout", Color32::from_rgb(230, 156, 71), "🍕", Some(food.id));
+        let personal = Category::new("Personal care", Color32::from_rgb(110, 133, 255), "🧴", None);
+        let other = Category::new("Other", Color32::from_rgb(180, 180, 200), "📦", None);
+        self.data.transactions = vec![
+            Transaction {
+                id: Uuid::new_v4(),
+                title: "teva overflow".to_string(),
+                date: NaiveDate::from_ymd_opt(2023, 8, 31).unwrap_or_default(),
+                time: None,
+                amount: Money::from_cents(-45800, Currency::PHP),
+                category: Some(shopping.id),
+                account: cash.id,
+                recurring: None,
+                splits: Vec::new(),
+                transfer_to: None,
+                import_id: None,
+            },
+            Transaction {
+                id: Uuid::new_v4(),
+                title: "mcdonald".to_string(),
+                date: NaiveDate::from_ymd_opt(2023, 8, 30).unwrap_or_default(),
+                time: None,
+                amount: Money::from_cents(-11946, Currency::PHP),
+                category: Some(food.id),
+                account: bank.id,
+                recurring: None,
+                splits: Vec::new(),
+                transfer_to: None,
+                import_id: None,
+            },
+            Transaction {
+                id: Uuid::new_v4(),
+                title: "bath and bodyworks".to_string(),
+                date: NaiveDate::from_ymd_opt(2023, 8, 30).unwrap_or_default(),
+                time: None,
+                amount: Money::from_cents(-8000, Currency::PHP),
+                category: Some(personal.id),
+                account: cash.id,
+                recurring: None,
+                splits: Vec::new(),
+                transfer_to: None,
+                import_id: None,
+            },
+            Transaction {
+                id: Uuid::new_v4(),
+                title: "dominos pizza".to_string(),
+                date: NaiveDate::from_ymd_opt(2023, 8, 28).unwrap_or_default(),
+                time: None,
+                amount: Money::from_cents(-8100, Currency::PHP),
+                category: Some(takeout.id),
+                account: bank.id,
+                recurring: None,
+                splits: Vec::new(),
+                transfer_to: None,
+                import_id: None,
+            },
+            Transaction {
+                id: Uuid::new_v4(),
+                title: "dr.locker".to_string(),
+                date: NaiveDate::from_ymd_opt(2023, 8, 28).unwrap_or_default(),
+                time: None,
+                amount: Money::from_cents(-4000, Currency::PHP),
+                category: Some(other.id),
+                account: cash.id,
+                recurring: None,
+                splits: Vec::new(),
+                transfer_to: None,
+                import_id: None,
+            },
+        ];
+        self.data.accounts = vec![cash, bank];
+        self.data.categories = vec![shopping, food, takeout, personal, other];
+    }
+
+    fn catch_up_on_launch(&mut self) {
+        let generated = self.catch_up_recurring();
+        if generated > 0 {
+            self.status = format!("Added {} recurring transaction(s) since the last launch.", generated);
+            self.save();
+        }
     }
 
-    fn remaining(&self) -> f64 {
-        self.monthly_budget - self.total_spent()
+    /// Sum of positive amounts in the budget's currency in the selected period.
+    fn total_income(&self) -> Money {
+        model::period_income(&self.data.transactions, self.period, self.data.budget.currency)
+    }
+
+    /// Sum of negative amounts in the budget's currency in the selected period, as a positive figure.
+    fn total_expenses(&self) -> Money {
+        model::period_expenses(&self.data.transactions, self.period, self.data.budget.currency)
+    }
+
+    fn net_cashflow(&self) -> Money {
+        self.total_income() - self.total_expenses()
+    }
+
+    /// The balance of all accounts, one sum per currency in the order the
+    /// currencies first appear.
+    fn total_balances(&self) -> Vec<Money> {
+        let mut totals: Vec<Money> = Vec::new();
+        for account in &self.data.accounts {
+            let balance = model::account_balance(account, &self.data.transactions);
+            match totals.iter_mut().find(|t| t.currency() == account.opening_balance.currency()) {
+                Some(total) => *total += balance,
+                None => totals.push(Money::zero(account.opening_balance.currency()) + balance),
+            }
+        }
+        totals
+    }
+
+    fn account_name(&self, id: Uuid) -> &str {
+        self.data.accounts
+            .iter()
+            .find(|a| a.id == id)
+            .map(|a| a.name.as_str())
+            .unwrap_or("Unknown account")
+    }
+
+    fn category(&self, id: Uuid) -> Option<&Category> {
+        self.data.categories.iter().find(|c| c.id == id)
+    }
+
+    fn remaining(&self) -> Money {
+        self.data.budget.available_for(self.period, &self.data.transactions) - self.total_expenses()
+    }
+
+    /// Lets the budget follow any currency an account is kept in. Only shown
+    /// when there is a choice.
+    fn budget_currency_picker(&mut self, ui: &mut egui::Ui) {
+        let current = self.data.budget.currency;
+        let mut currencies = vec![current];
+        for account in &self.data.accounts {
+            if !currencies.contains(&account.opening_balance.currency()) {
+                currencies.push(account.opening_balance.currency());
+            }
+        }
+        if currencies.len() < 2 {
+            ui.label(RichText::new(current.as_str()).color(Color32::from_rgb(180, 185, 195)));
+            return;
+        }
+        let mut selected = current;
+        egui::ComboBox::from_id_source("budget_currency")
+            .selected_text(current.as_str())
+            .width(60.0)
+            .show_ui(ui, |ui| {
+                for currency in currencies {
+                    ui.selectable_value(&mut selected, currency, currency.as_str());
+                }
+            });
+        if selected != current {
+            let before = self.data.budget.clone();
+            let after = before.in_currency(selected);
+            self.execute(Command::SetBudget { before, after });
+            self.budget_input = self.data.budget.amount_for(self.period).to_string();
+            self.status = format!("The budget is now in {}; only {} transactions count against it.", selected, selected);
+        }
+    }
+
+    fn select_period(&mut self, period: Period) {
+        self.period = period;
+        self.budget_input = self.data.budget.amount_for(period).to_string();
+    }
+
+    /// Transactions in the selected period that pass the list's filter,
+    /// ordered by date and time, newest first. Entries on the same day
+    /// without a time keep their insertion order (latest added first).
+    fn transactions_newest_first(&self) -> Vec<&Transaction> {
+        let mut sorted: Vec<&Transaction> = self
+            .data
+            .transactions
+            .iter()
+            .rev()
+            .filter(|tx| self.period.contains(tx.date) && self.tx_filter.matches(tx))
+            .collect();
+        sorted.sort_by_key(|tx| std::cmp::Reverse((tx.date, tx.time)));
+        sorted
+    }
+
+    /// Applies a change through the undo history and saves what it touched.
+    fn execute(&mut self, command: Command) {
+        let touched = command.touched();
+        self.history.execute(command, &mut self.data);
+        self.save_changes(&touched);
+    }
+
+    fn undo(&mut self) {
+        match self.history.undo(&mut self.data).map(|c| (c.label(), c.touched())) {
+            Some((label, touched)) => {
+                self.save_changes(&touched);
+                self.after_history_step();
+                self.status = format!("Undid: {}", label);
+            }
+            None => self.status = "Nothing to undo.".to_string(),
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.history.redo(&mut self.data).map(|c| (c.label(), c.touched())) {
+            Some((label, touched)) => {
+                self.save_changes(&touched);
+                self.after_history_step();
+                self.status = format!("Redid: {}", label);
+            }
+            None => self.status = "Nothing to redo.".to_string(),
+        }
+    }
+
+    /// Undo/redo can remove accounts or categories the UI still has selected.
+    fn after_history_step(&mut self) {
+        let fallback = self.data.accounts.first().map(|a| a.id).unwrap_or_default();
+        for selected in [
+            &mut self.entry_account,
+            &mut self.form_account,
+            &mut self.form_transfer_to,
+            &mut self.rule_form_account,
+        ] {
+            if !self.data.accounts.iter().any(|a| a.id == *selected) {
+                *selected = fallback;
+            }
+        }
+        for selected in [&mut self.entry_category, &mut self.form_category] {
+            if selected.is_some_and(|id| !self.data.categories.iter().any(|c| c.id == id && !c.archived)) {
+                *selected = None;
+            }
+        }
+        if self.editing_tx.is_some_and(|id| !self.data.transactions.iter().any(|t| t.id == id)) {
+            self.editing_tx = None;
+            self.show_new_tx = false;
+        }
+        if self.editing_account.is_some_and(|id| !self.data.accounts.iter().any(|a| a.id == id)) {
+            self.clear_account_form();
+        }
+        if self.editing_category.is_some_and(|id| self.category(id).is_none()) {
+            self.clear_category_form();
+        }
+        self.budget_input = self.data.budget.amount_for(self.period).to_string();
+    }
+
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        // Text fields keep their own Ctrl+Z while they have focus.
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let redo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z);
+        let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+        if ctx.input_mut(|i| i.consume_shortcut(&redo)) {
+            self.redo();
+        } else if ctx.input_mut(|i| i.consume_shortcut(&undo)) {
+            self.undo();
+        }
+    }
+
+    /// Generates every recurring transaction that fell due while the app was
+    /// closed. This runs at startup and is not part of the undo history.
+    fn catch_up_recurring(&mut self) -> usize {
+        let today = date::today();
+        let mut generated = 0;
+        for rule in &mut self.data.recurring {
+            let (transactions, updated) = recurring::catch_up(rule, today);
+            generated += transactions.len();
+            self.data.transactions.extend(transactions);
+            *rule = updated;
+        }
+        generated
+    }
+
+    /// Scheduled occurrences in the next `days` days that have not been
+    /// generated yet, soonest first.
+    fn upcoming_recurring(&self, days: i64, limit: usize) -> Vec<(NaiveDate, &RecurringRule)> {
+        let until = date::today() + chrono::Duration::days(days);
+        let mut upcoming: Vec<(NaiveDate, &RecurringRule)> = self
+            .data
+            .recurring
+            .iter()
+            .flat_map(|rule| {
+                rule.occurrences(rule.last_generated, until)
+                    .into_iter()
+                    .map(move |due| (due, rule))
+            })
+            .collect();
+        upcoming.sort_by_key(|(due, _)| *due);
+        upcoming.truncate(limit);
+        upcoming
+    }
+
+    fn set_rollover(&mut self, enabled: bool) {
+        let before = self.data.budget.clone();
+        let mut after = before.clone();
+        after.rollover_from = enabled.then_some(self.period);
+        self.execute(Command::SetBudget { before, after });
+        self.status = if enabled {
+            format!("Unspent budget now rolls over starting {}.", self.period.label())
+        } else {
+            "Budget rollover turned off.".to_string()
+        };
     }
 
     fn update_budget_from_input(&mut self) {
-        match self.budget_input.trim().parse::<f64>() {
-            Ok(val) if val >= 0.0 => {
-                self.monthly_budget = val;
-                self.status = format!("Monthly budget set to ${:.2}", val);
-                let _ = self.save_state();
+        match Money::parse(&self.budget_input, self.data.budget.currency) {
+            Ok(val) if !val.is_negative() => {
+                let before = self.data.budget.clone();
+                let mut after = before.clone();
+                after.periods.insert(self.period, val);
+                // Months without their own budget follow the current month's.
+                if self.period == Period::current() {
+                    after.default_amount = val;
+                }
+                self.execute(Command::SetBudget { before, after });
+                self.status = format!("Budget for {} set to {} {:#}", self.period.label(), val.currency(), val);
             }
             _ => {
                 self.status = "Enter a valid non-negative number for the monthly budget.".to_string();
@@ -132,8 +1255,9 @@ impl BudgetApp {
     }
 
     fn add_entry(&mut self) {
-        let amount = match self.entry_amount.trim().parse::<f64>() {
-            Ok(val) if val > 0.0 => val,
+        let currency = Self::account_currency(&self.data.accounts, self.entry_account);
+        let amount = match Money::parse(&self.entry_amount, currency) {
+            Ok(val) if val.is_positive() => val,
             _ => {
                 self.status = "Enter a positive number for the amount.".to_string();
                 return;
@@ -145,17 +1269,23 @@ impl BudgetApp {
             return;
         }
 
-        self.transactions.push(Transaction {
+        self.execute(Command::AddTransaction(Transaction {
+            id: Uuid::new_v4(),
             title: self.entry_description.trim().to_string(),
-            date: "Today".to_string(),
+            date: date::today(),
+            time: Some(date::now_time()),
             amount: -amount,
-            color: Color32::from_rgb(88, 172, 255),
-        });
+            category: self.entry_category,
+            account: self.entry_account,
+            recurring: None,
+            splits: Vec::new(),
+            transfer_to: None,
+            import_id: None,
+        }));
 
         self.entry_description.clear();
         self.entry_amount.clear();
         self.status = "Entry added.".to_string();
-        let _ = self.save_state();
     }
 
     fn apply_style(&self, ctx: &egui::Context) {
@@ -184,7 +1314,7 @@ impl BudgetApp {
         ctx.set_visuals(visuals);
     }
 
-    fn nav_button(ui: &mut egui::Ui, label: &str, selected: bool) {
+    fn nav_button(ui: &mut egui::Ui, label: &str, selected: bool) -> bool {
         let bg = if selected {
             Color32::from_rgb(50, 50, 60)
         } else {
@@ -198,154 +1328,2931 @@ impl BudgetApp {
             .rounding(egui::Rounding::same(6.0))
             .inner_margin(egui::Margin::symmetric(10.0, 8.0))
             .show(ui, |ui| {
+                // Selectable labels would swallow the click meant for the button.
+                ui.style_mut().interaction.selectable_labels = false;
+                ui.set_width(ui.available_width());
                 ui.label(text);
-            });
+            })
+            .response
+            .interact(egui::Sense::click())
+            .on_hover_cursor(egui::CursorIcon::PointingHand)
+            .clicked()
     }
 
-    fn stat_card(ui: &mut egui::Ui, title: &str, amount: f64, accent: Color32) {
-        egui::Frame::none()
+    fn signed_color(amount: Money) -> Color32 {
+        if amount.is_negative() {
+            Color32::from_rgb(240, 80, 100)
+        } else {
+            Color32::from_rgb(110, 220, 140)
+        }
+    }
+
+    fn figure(ui: &mut egui::Ui, label: &str, amount: Money, color: Color32) {
+        ui.vertical(|ui| {
+            ui.label(RichText::new(label).color(Color32::from_rgb(160, 165, 175)).size(13.0));
+            ui.label(RichText::new(format!("{} {:#}", amount.currency(), amount)).color(color).strong());
+        });
+    }
+
+    fn stat_card(ui: &mut egui::Ui, title: &str, amount: Money, accent: Color32) {
+        egui::Frame::none()
             .fill(Color32::from_rgb(35, 35, 45))
             .rounding(egui::Rounding::same(12.0))
-            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(60, 60, 80)))
-            .inner_margin(egui::Margin::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(60, 60, 80)))
+            .inner_margin(egui::Margin::same(12.0))
+            .show(ui, |ui| {
+                ui.label(
+                    RichText::new(title)
+                        .color(Color32::from_rgb(190, 195, 210))
+                        .size(13.0),
+                );
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(amount.currency().as_str()).color(Color32::from_rgb(190, 195, 210)));
+                    ui.label(
+                        RichText::new(format!("{:+#}", amount))
+                            .color(accent)
+                            .size(20.0)
+                            .strong(),
+                    );
+                });
+            });
+    }
+
+    /// Draws one transaction; returns true when the row was clicked.
+    /// For transfers `account` is the "From → To" label and the amount is
+    /// shown unsigned.
+    fn transaction_row(ui: &mut egui::Ui, tx: &Transaction, account: &str, category: Option<&Category>) -> bool {
+        let dot = category.map_or(model::UNCATEGORIZED_COLOR, |c| c.color);
+        let category_label = if tx.is_transfer() {
+            "Transfer".to_string()
+        } else if tx.splits.is_empty() {
+            category.map_or_else(
+                || "Uncategorized".to_string(),
+                |c| format!("{} {}", c.icon, c.name).trim().to_string(),
+            )
+        } else {
+            format!("Split ({} lines)", tx.splits.len())
+        };
+        egui::Frame::none()
+            .fill(Color32::from_rgb(32, 32, 40))
+            .rounding(egui::Rounding::same(10.0))
+            .inner_margin(egui::Margin::symmetric(12.0, 10.0))
+            .show(ui, |ui| {
+                ui.style_mut().interaction.selectable_labels = false;
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new("●")
+                            .color(dot)
+                            .size(22.0)
+                            .family(egui::FontFamily::Monospace),
+                    );
+                    ui.vertical(|ui| {
+                        ui.label(
+                            RichText::new(tx.title.to_owned())
+                                .color(Color32::from_rgb(220, 225, 235))
+                                .strong(),
+                        );
+                        ui.label(
+                            RichText::new(format!(
+                                "{} · {} · {}",
+                                date::format_date(tx.date, tx.time),
+                                account,
+                                category_label
+                            ))
+                                .color(Color32::from_rgb(150, 155, 165))
+                                .size(12.0),
+                        );
+                    });
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        let (amount, color) = if tx.is_transfer() {
+                            (format!("⇄ {:#}", tx.amount), Color32::from_rgb(127, 138, 255))
+                        } else {
+                            (format!("{:+#}", tx.amount), Self::signed_color(tx.amount))
+                        };
+                        ui.label(RichText::new(amount).color(color).strong());
+                    });
+                });
+            })
+            .response
+            .interact(egui::Sense::click())
+            .on_hover_cursor(egui::CursorIcon::PointingHand)
+            .on_hover_text("Click to edit")
+            .clicked()
+    }
+
+    fn account_picker(ui: &mut egui::Ui, id_source: &str, accounts: &[Account], selected: &mut Uuid) {
+        let current = accounts
+            .iter()
+            .find(|a| a.id == *selected)
+            .map(|a| a.name.clone())
+            .unwrap_or_else(|| "Select account".to_string());
+        egui::ComboBox::from_id_source(id_source)
+            .selected_text(current)
+            .show_ui(ui, |ui| {
+                for account in accounts {
+                    ui.selectable_value(selected, account.id, &account.name);
+                }
+            });
+    }
+
+    /// Picker over active categories; archived ones only appear while selected.
+    fn category_picker(ui: &mut egui::Ui, id_source: &str, categories: &[Category], selected: &mut Option<Uuid>) {
+        let current = selected
+            .map(|id| model::category_path(categories, id))
+            .filter(|path| !path.is_empty())
+            .unwrap_or_else(|| "Uncategorized".to_string());
+        egui::ComboBox::from_id_source(id_source)
+            .selected_text(current)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(selected, None, "Uncategorized");
+                for category in categories.iter().filter(|c| !c.archived) {
+                    let label = format!("{} {}", category.icon, model::category_path(categories, category.id));
+                    ui.selectable_value(selected, Some(category.id), label.trim());
+                }
+            });
+    }
+
+    /// Writes the budget file. Refused while recovery is pending so the
+    /// unreadable original is never overwritten behind the user's back.
+    fn save_state(&mut self) -> Result<(), String> {
+        if self.unlock.is_some() {
+            return Err("The budget is locked; enter its passphrase first.".to_string());
+        }
+        if self.recovery.is_some() {
+            return Err("The data file could not be read; choose a recovery option first.".to_string());
+        }
+        self.store.save(&PersistedState::from(&self.data))
+    }
+
+    /// Saves and keeps any failure on screen until a later save succeeds.
+    /// Held back while an outside change to the file is unresolved.
+    fn save(&mut self) {
+        if self.disk_changed {
+            return;
+        }
+        self.save_error = self.save_state().err();
+        self.after_write();
+    }
+
+    /// Saves only the `touched` records. After a failed save everything is
+    /// written again, since earlier changes may be missing from the file.
+    fn save_changes(&mut self, touched: &[Touched]) {
+        if self.save_error.is_some() || self.recovery.is_some() || self.unlock.is_some() || self.disk_changed {
+            self.save();
+            return;
+        }
+        self.save_error = self.store.save_changes(touched, &self.data).err();
+        self.after_write();
+    }
+
+    /// Remembers what the file now holds, so a later outside change is
+    /// merged against what we wrote rather than what we last read.
+    fn after_write(&mut self) {
+        self.disk_stamp = storage::stamp(&self.data_path);
+        if self.save_error.is_none() {
+            self.synced = Some(PersistedState::from(&self.data));
+        }
+    }
+
+    /// Looks at the data file about once a second for writes by someone
+    /// else, such as a sync client updating a shared folder.
+    fn check_disk(&mut self, ctx: &egui::Context) {
+        ctx.request_repaint_after(DISK_CHECK_INTERVAL);
+        if self.disk_changed || self.last_disk_check.elapsed() < DISK_CHECK_INTERVAL {
+            return;
+        }
+        self.last_disk_check = Instant::now();
+        if storage::stamp(&self.data_path) != self.disk_stamp {
+            self.disk_changed = true;
+        }
+    }
+
+    /// Throws away what is in memory and reads the data file again.
+    fn reload_from_disk(&mut self) {
+        self.disk_changed = false;
+        let loaded = self.store.load();
+        self.data = BudgetData::default();
+        self.history = History::default();
+        self.apply_loaded(loaded, false);
+        self.after_history_step();
+        if self.recovery.is_none() {
+            self.status = "Reloaded the budget from disk.".to_string();
+        }
+    }
+
+    /// Re-applies the changes made here since the file was last read on top
+    /// of the version on disk, then saves the result.
+    fn merge_with_disk(&mut self) {
+        let theirs = match self.store.load() {
+            Ok(Some((mut theirs, _))) => {
+                theirs.normalize();
+                theirs
+            }
+            Ok(None) => {
+                self.status = "The data file was deleted; keep your version to write it again.".to_string();
+                return;
+            }
+            Err(err) => {
+                self.status = format!("The file on disk could not be read: {}", err);
+                return;
+            }
+        };
+        let mine = PersistedState::from(&self.data);
+        let base = self.synced.clone().unwrap_or_else(|| theirs.clone());
+        let merge = merge::three_way(&base, &mine, &theirs);
+        if merge.conflicts.is_empty() {
+            self.apply_merge(&merge, true);
+        } else {
+            self.merge_dialog = Some(MergeDialog {
+                merge: Some(merge),
+                from_disk: true,
+                ..MergeDialog::default()
+            });
+        }
+    }
+
+    /// Replaces the budget with the result of `merge` and saves it.
+    fn apply_merge(&mut self, merge: &Merge, from_disk: bool) {
+        let mut merged = match merge.finish() {
+            Ok(merged) => merged,
+            Err(err) => {
+                self.status = err;
+                return;
+            }
+        };
+        merged.normalize();
+        if from_disk {
+            self.synced = Some(merged.clone());
+            self.disk_changed = false;
+        }
+        self.data = merged.into();
+        // Undo steps were recorded against the old data.
+        self.history = History::default();
+        self.after_history_step();
+        self.save();
+        self.status = format!(
+            "Merged {}; {} conflict(s) resolved.",
+            if from_disk { "your changes into the version on disk" } else { "the two budgets" },
+            merge.conflicts.len()
+        );
+    }
+
+    fn csv_import_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.csv_import else {
+            return;
+        };
+        let mut open = true;
+        let mut reload = None;
+        let mut import = false;
+        egui::Window::new("Import CSV")
+            .id(egui::Id::new("csv_import"))
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(620.0)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut dialog.path)
+                            .hint_text("Path to the bank's CSV statement")
+                            .desired_width(440.0),
+                    );
+                    if ui.button("Load").clicked() && !dialog.path.trim().is_empty() {
+                        reload = Some(true);
+                    }
+                });
+                let Some(table) = &dialog.table else {
+                    return;
+                };
+                let columns: Vec<String> = table
+                    .headers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, header)| if header.is_empty() { format!("Column {}", i + 1) } else { header.clone() })
+                    .collect();
+
+                ui.horizontal(|ui| {
+                    ui.label("Delimiter");
+                    let current = csv_import::DELIMITERS
+                        .iter()
+                        .find(|(d, _)| *d == dialog.delimiter)
+                        .map_or("Other", |(_, label)| *label);
+                    egui::ComboBox::from_id_source("csv_delimiter")
+                        .selected_text(current)
+                        .show_ui(ui, |ui| {
+                            for (delimiter, label) in csv_import::DELIMITERS {
+                                if ui.selectable_value(&mut dialog.delimiter, delimiter, label).changed() {
+                                    reload = Some(false);
+                                }
+                            }
+                        });
+                    if ui.checkbox(&mut dialog.has_header, "First row is a header").changed() {
+                        reload = Some(false);
+                    }
+                });
+
+                ui.add_space(6.0);
+                egui::ScrollArea::horizontal().id_source("csv_preview").show(ui, |ui| {
+                    egui::Grid::new("csv_raw").striped(true).show(ui, |ui| {
+                        for column in &columns {
+                            ui.label(RichText::new(column).strong());
+                        }
+                        ui.end_row();
+                        for row in table.rows.iter().take(6) {
+                            for cell in row {
+                                ui.label(cell);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                ui.add_space(6.0);
+                let column_picker = |ui: &mut egui::Ui, id: &str, selected: &mut usize| {
+                    egui::ComboBox::from_id_source(id)
+                        .selected_text(columns.get(*selected).cloned().unwrap_or_default())
+                        .show_ui(ui, |ui| {
+                            for (index, column) in columns.iter().enumerate() {
+                                ui.selectable_value(selected, index, column);
+                            }
+                        });
+                };
+                egui::Grid::new("csv_mapping").num_columns(2).show(ui, |ui| {
+                    ui.label("Date");
+                    ui.horizontal(|ui| {
+                        column_picker(ui, "csv_date", &mut dialog.date);
+                        egui::ComboBox::from_id_source("csv_date_format")
+                            .selected_text(dialog.date_format.clone())
+                            .show_ui(ui, |ui| {
+                                for format in csv_import::DATE_FORMATS {
+                                    ui.selectable_value(&mut dialog.date_format, format.to_string(), format);
+                                }
+                            });
+                        ui.add(egui::TextEdit::singleline(&mut dialog.date_format).desired_width(90.0))
+                            .on_hover_text("chrono format: %d day, %m month, %Y year, %b month name");
+                    });
+                    ui.end_row();
+                    ui.label("Description");
+                    column_picker(ui, "csv_description", &mut dialog.description);
+                    ui.end_row();
+                    ui.label("Amount");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut dialog.debit_credit, false, "One signed column");
+                        ui.radio_value(&mut dialog.debit_credit, true, "Debit and credit columns");
+                    });
+                    ui.end_row();
+                    if dialog.debit_credit {
+                        ui.label("Debit (money out)");
+                        column_picker(ui, "csv_debit", &mut dialog.debit);
+                        ui.end_row();
+                        ui.label("Credit (money in)");
+                        column_picker(ui, "csv_credit", &mut dialog.credit);
+                        ui.end_row();
+                    } else {
+                        ui.label("");
+                        column_picker(ui, "csv_amount", &mut dialog.amount);
+                        ui.end_row();
+                    }
+                    ui.label("");
+                    ui.checkbox(&mut dialog.decimal_comma, "Decimal comma (1.234,56)");
+                    ui.end_row();
+                    ui.label("Into account");
+                    Self::account_picker(ui, "csv_account", &self.data.accounts, &mut dialog.account);
+                    ui.end_row();
+                });
+
+                let currency = Self::account_currency(&self.data.accounts, dialog.account);
+                let (ready, skipped) = csv_import::transactions(table, &dialog.mapping(), dialog.account, currency);
+                ui.add_space(6.0);
+                ui.label(RichText::new("Preview").strong());
+                egui::Grid::new("csv_result").striped(true).show(ui, |ui| {
+                    for tx in ready.iter().take(5) {
+                        ui.label(date::format_date(tx.date, None));
+                        ui.label(&tx.title);
+                        ui.label(format!("{:+#}", tx.amount));
+                        ui.end_row();
+                    }
+                });
+                for problem in skipped.iter().take(3) {
+                    ui.label(RichText::new(problem).color(Color32::from_rgb(235, 120, 120)).size(12.0));
+                }
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    let label = format!("Import {} transaction(s)", ready.len());
+                    import = ui
+                        .add_enabled(
+                            !ready.is_empty(),
+                            egui::Button::new(RichText::new(label).color(Color32::WHITE))
+                                .fill(Color32::from_rgb(92, 106, 255)),
+                        )
+                        .clicked();
+                    if !skipped.is_empty() {
+                        ui.label(format!("{} row(s) will be skipped.", skipped.len()));
+                    }
+                });
+            });
+        if let Some(guess_delimiter) = reload
+            && let Err(err) = dialog.load(guess_delimiter)
+        {
+            dialog.table = None;
+            self.status = err;
+        }
+        if import {
+            self.import_csv();
+        } else if !open {
+            self.csv_import = None;
+        }
+    }
+
+    fn account_currency(accounts: &[Account], account: Uuid) -> Currency {
+        accounts
+            .iter()
+            .find(|a| a.id == account)
+            .map_or_else(Currency::default, |a| a.opening_balance.currency())
+    }
+
+    /// Appends the mapped rows as one undoable step.
+    fn import_csv(&mut self) {
+        let Some(dialog) = self.csv_import.take() else {
+            return;
+        };
+        let Some(table) = &dialog.table else {
+            return;
+        };
+        let currency = Self::account_currency(&self.data.accounts, dialog.account);
+        let (imported, skipped) = csv_import::transactions(table, &dialog.mapping(), dialog.account, currency);
+        let count = imported.len();
+        let file = PathBuf::from(dialog.path.trim());
+        let name = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        self.execute(Command::Batch {
+            label: format!("Import {} transaction(s) from {}", count, name),
+            commands: imported.into_iter().map(Command::AddTransaction).collect(),
+        });
+        self.status = if skipped.is_empty() {
+            format!("Imported {} transaction(s) from {}.", count, name)
+        } else {
+            format!("Imported {} transaction(s) from {}; skipped {} row(s).", count, name, skipped.len())
+        };
+    }
+
+    fn ofx_import_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.ofx_import else {
+            return;
+        };
+        let mut open = true;
+        let mut load = false;
+        let mut import = false;
+        egui::Window::new("Import OFX/QFX")
+            .id(egui::Id::new("ofx_import"))
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(520.0)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut dialog.path)
+                            .hint_text("Path to the bank's .ofx or .qfx statement")
+                            .desired_width(380.0),
+                    );
+                    if ui.button("Load").clicked() && !dialog.path.trim().is_empty() {
+                        load = true;
+                    }
+                });
+                let Some(statement) = &dialog.statement else {
+                    return;
+                };
+                ui.add_space(6.0);
+                egui::Grid::new("ofx_summary").num_columns(2).show(ui, |ui| {
+                    if let Some(account_id) = &statement.account_id {
+                        ui.label("Bank account");
+                        ui.label(account_id);
+                        ui.end_row();
+                    }
+                    ui.label("Transactions");
+                    ui.label(statement.lines.len().to_string());
+                    ui.end_row();
+                    if let Some(balance) = statement.ledger_balance {
+                        ui.label("Ledger balance");
+                        ui.label(format!("{:+#} on {}", balance.amount, date::format_date(balance.date, None)));
+                        ui.end_row();
+                    }
+                    ui.label("Into account");
+                    Self::account_picker(ui, "ofx_account", &self.data.accounts, &mut dialog.account);
+                    ui.end_row();
+                });
+
+                let fresh = statement.new_lines(dialog.account, &self.data.transactions);
+                let currency = Self::account_currency(&self.data.accounts, dialog.account);
+                let mismatch = statement.currency().filter(|c| *c != currency);
+                ui.add_space(6.0);
+                egui::Grid::new("ofx_preview").striped(true).show(ui, |ui| {
+                    for line in fresh.iter().take(5) {
+                        ui.label(date::format_date(line.date, None));
+                        ui.label(if line.name.is_empty() { &line.memo } else { &line.name });
+                        ui.label(format!("{:+#}", line.amount));
+                        ui.end_row();
+                    }
+                });
+                let already = statement.lines.len() - fresh.len();
+                if already > 0 {
+                    ui.label(format!("{} transaction(s) were imported before and will be skipped.", already));
+                }
+                for problem in statement.skipped.iter().take(3) {
+                    ui.label(RichText::new(problem).color(Color32::from_rgb(235, 120, 120)).size(12.0));
+                }
+                if !statement.skipped.is_empty() {
+                    ui.label(format!("{} unreadable transaction(s) will be skipped.", statement.skipped.len()));
+                }
+                if let Some(other) = mismatch {
+                    ui.label(
+                        RichText::new(format!("The statement is in {} but the account is in {}.", other, currency))
+                            .color(Color32::from_rgb(235, 120, 120)),
+                    );
+                }
+                ui.add_space(6.0);
+                let label = format!("Import {} transaction(s)", fresh.len());
+                import = ui
+                    .add_enabled(
+                        mismatch.is_none() && (!fresh.is_empty() || statement.ledger_balance.is_some()),
+                        egui::Button::new(RichText::new(label).color(Color32::WHITE))
+                            .fill(Color32::from_rgb(92, 106, 255)),
+                    )
+                    .clicked();
+            });
+        if load {
+            match ofx::read(std::path::Path::new(dialog.path.trim())) {
+                Ok(statement) => dialog.statement = Some(statement),
+                Err(err) => {
+                    dialog.statement = None;
+                    self.status = err;
+                }
+            }
+        }
+        if import {
+            self.import_ofx();
+        } else if !open {
+            self.ofx_import = None;
+        }
+    }
+
+    /// Adds the statement's new transactions and remembers its ledger
+    /// balance on the account, as one undoable step.
+    fn import_ofx(&mut self) {
+        let Some(dialog) = self.ofx_import.take() else {
+            return;
+        };
+        let Some(statement) = &dialog.statement else {
+            return;
+        };
+        let mut commands: Vec<Command> = statement
+            .new_lines(dialog.account, &self.data.transactions)
+            .into_iter()
+            .map(|line| Command::AddTransaction(line.to_transaction(dialog.account)))
+            .collect();
+        let count = commands.len();
+        if let Some(balance) = statement.ledger_balance
+            && let Some(before) = self.data.accounts.iter().find(|a| a.id == dialog.account)
+            && before.statement_balance.is_none_or(|b| b.date <= balance.date)
+        {
+            commands.push(Command::EditAccount {
+                before: before.clone(),
+                after: Account {
+                    statement_balance: Some(balance),
+                    ..before.clone()
+                },
+            });
+        }
+        let file = PathBuf::from(dialog.path.trim());
+        let name = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let already = statement.lines.len() - count;
+        self.execute(Command::Batch {
+            label: format!("Import {} transaction(s) from {}", count, name),
+            commands,
+        });
+        self.status = format!("Imported {} transaction(s) from {}", count, name);
+        if already > 0 {
+            self.status.push_str(&format!("; {} were already there", already));
+        }
+        if !statement.skipped.is_empty() {
+            self.status.push_str(&format!("; skipped {} unreadable one(s)", statement.skipped.len()));
+        }
+        self.status.push('.');
+    }
+
+    fn qif_import_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.qif_import else {
+            return;
+        };
+        let mut open = true;
+        let mut load = false;
+        let mut import = false;
+        egui::Window::new("Import QIF")
+            .id(egui::Id::new("qif_import"))
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(520.0)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut dialog.path)
+                            .hint_text("Path to the .qif file")
+                            .desired_width(380.0),
+                    );
+                    if ui.button("Load").clicked() && !dialog.path.trim().is_empty() {
+                        load = true;
+                    }
+                });
+                if ui.checkbox(&mut dialog.day_first, "Dates are written day first (31/01/2024)").changed() {
+                    load = dialog.file.is_some();
+                }
+                let Some(file) = &dialog.file else {
+                    return;
+                };
+                let result = qif::import(
+                    file,
+                    &self.data.accounts,
+                    &self.data.categories,
+                    dialog.account,
+                    DEFAULT_CATEGORY_COLOR,
+                );
+                ui.add_space(6.0);
+                egui::Grid::new("qif_summary").num_columns(2).show(ui, |ui| {
+                    ui.label("Transactions");
+                    ui.label(result.transactions.len().to_string());
+                    ui.end_row();
+                    if !result.accounts.is_empty() {
+                        ui.label("New accounts");
+                        let names: Vec<&str> = result.accounts.iter().map(|a| a.name.as_str()).collect();
+                        ui.label(names.join(", "));
+                        ui.end_row();
+                    }
+                    if !result.categories.is_empty() {
+                        ui.label("New categories");
+                        ui.label(result.categories.len().to_string());
+                        ui.end_row();
+                    }
+                    if file.sections.iter().any(|s| s.account.is_none()) {
+                        ui.label("Into account");
+                        Self::account_picker(ui, "qif_account", &self.data.accounts, &mut dialog.account);
+                        ui.end_row();
+                    }
+                });
+                for problem in file.skipped.iter().take(3) {
+                    ui.label(RichText::new(problem).color(Color32::from_rgb(235, 120, 120)).size(12.0));
+                }
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    let label = format!("Import {} transaction(s)", result.transactions.len());
+                    import = ui
+                        .add_enabled(
+                            !result.transactions.is_empty() || !result.accounts.is_empty(),
+                            egui::Button::new(RichText::new(label).color(Color32::WHITE))
+                                .fill(Color32::from_rgb(92, 106, 255)),
+                        )
+                        .clicked();
+                    if !file.skipped.is_empty() {
+                        ui.label(format!("{} record(s) will be skipped.", file.skipped.len()));
+                    }
+                });
+            });
+        if load {
+            match qif::read(std::path::Path::new(dialog.path.trim()), dialog.day_first) {
+                Ok(file) => dialog.file = Some(file),
+                Err(err) => {
+                    dialog.file = None;
+                    self.status = err;
+                }
+            }
+        }
+        if import {
+            self.import_qif();
+        } else if !open {
+            self.qif_import = None;
+        }
+    }
+
+    /// Adds the file's new accounts, categories and transactions as one
+    /// undoable step.
+    fn import_qif(&mut self) {
+        let Some(dialog) = self.qif_import.take() else {
+            return;
+        };
+        let Some(file) = &dialog.file else {
+            return;
+        };
+        let result = qif::import(
+            file,
+            &self.data.accounts,
+            &self.data.categories,
+            dialog.account,
+            DEFAULT_CATEGORY_COLOR,
+        );
+        let count = result.transactions.len();
+        let name = PathBuf::from(dialog.path.trim())
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let commands = result
+            .accounts
+            .into_iter()
+            .map(Command::AddAccount)
+            .chain(result.categories.into_iter().map(Command::AddCategory))
+            .chain(result.transactions.into_iter().map(Command::AddTransaction))
+            .collect();
+        self.execute(Command::Batch {
+            label: format!("Import {} transaction(s) from {}", count, name),
+            commands,
+        });
+        self.status = if file.skipped.is_empty() {
+            format!("Imported {} transaction(s) from {}.", count, name)
+        } else {
+            format!("Imported {} transaction(s) from {}; skipped {} record(s).", count, name, file.skipped.len())
+        };
+    }
+
+    fn export_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.export else {
+            return;
+        };
+        let mut open = true;
+        let mut export = false;
+        let filter_active = self.tx_filter.is_active();
+        egui::Window::new(format!("Export {}", dialog.format.label()))
+            .id(egui::Id::new("export"))
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(460.0)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Save to");
+                    ui.add(egui::TextEdit::singleline(&mut dialog.path).desired_width(360.0));
+                });
+                if dialog.format == ExportFormat::Csv {
+                    ui.add_space(6.0);
+                    Self::csv_export_options(ui, dialog, filter_active);
+                }
+                ui.add_space(6.0);
+                export = ui
+                    .add_enabled(
+                        !dialog.path.trim().is_empty(),
+                        egui::Button::new(RichText::new("Export").color(Color32::WHITE))
+                            .fill(Color32::from_rgb(92, 106, 255)),
+                    )
+                    .clicked();
+            });
+        if export {
+            self.export_budget();
+        } else if !open {
+            self.export = None;
+        }
+    }
+
+    fn csv_export_options(ui: &mut egui::Ui, dialog: &mut ExportDialog, filter_active: bool) {
+        let options = &mut dialog.csv;
+        egui::Grid::new("csv_export_options").num_columns(2).show(ui, |ui| {
+            ui.label("Transactions");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut dialog.scope, ExportScope::All, "All");
+                ui.radio_value(&mut dialog.scope, ExportScope::Period, "Current period");
+                ui.add_enabled_ui(filter_active, |ui| {
+                    ui.radio_value(&mut dialog.scope, ExportScope::Filter, "Current filter");
+                });
+            });
+            ui.end_row();
+            ui.label("Columns");
+            ui.horizontal_wrapped(|ui| {
+                for column in csv_export::Column::ALL {
+                    let mut on = options.columns.contains(&column);
+                    if ui.checkbox(&mut on, column.label()).changed() {
+                        // Keep the columns in their usual order.
+                        options.columns = csv_export::Column::ALL
+                            .into_iter()
+                            .filter(|c| if *c == column { on } else { options.columns.contains(c) })
+                            .collect();
+                    }
+                }
+            });
+            ui.end_row();
+            ui.label("Date format");
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("csv_export_date_format")
+                    .selected_text(options.date_format.clone())
+                    .show_ui(ui, |ui| {
+                        for format in csv_import::DATE_FORMATS {
+                            ui.selectable_value(&mut options.date_format, format.to_string(), format);
+                        }
+                    });
+                ui.add(egui::TextEdit::singleline(&mut options.date_format).desired_width(90.0))
+                    .on_hover_text("chrono format: %d day, %m month, %Y year, %b month name");
+            });
+            ui.end_row();
+            ui.label("Numbers");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut options.decimal_comma, false, "1234.56");
+                if ui.radio_value(&mut options.decimal_comma, true, "1234,56").clicked()
+                    && options.delimiter == b','
+                {
+                    options.delimiter = b';';
+                }
+            });
+            ui.end_row();
+            ui.label("Delimiter");
+            let current = csv_import::DELIMITERS
+                .iter()
+                .find(|(d, _)| *d == options.delimiter)
+                .map_or("Other", |(_, label)| *label);
+            egui::ComboBox::from_id_source("csv_export_delimiter")
+                .selected_text(current)
+                .show_ui(ui, |ui| {
+                    for (delimiter, label) in csv_import::DELIMITERS {
+                        ui.selectable_value(&mut options.delimiter, delimiter, label);
+                    }
+                });
+            ui.end_row();
+            ui.label("");
+            ui.checkbox(&mut options.split_rows, "One row per split line");
+            ui.end_row();
+        });
+    }
+
+    /// Writes the export dialog's file, keeping the dialog open on failure.
+    fn export_budget(&mut self) {
+        let Some(dialog) = &self.export else {
+            return;
+        };
+        let path = PathBuf::from(dialog.path.trim());
+        let text = match dialog.format {
+            ExportFormat::Qif => Ok(qif::write(&PersistedState::from(&self.data))),
+            ExportFormat::Csv => {
+                let mut transactions: Vec<&Transaction> = match dialog.scope {
+                    ExportScope::All => self.data.transactions.iter().collect(),
+                    ExportScope::Period => {
+                        self.data.transactions.iter().filter(|tx| self.period.contains(tx.date)).collect()
+                    }
+                    ExportScope::Filter => self.transactions_newest_first(),
+                };
+                transactions.sort_by_key(|tx| (tx.date, tx.time));
+                csv_export::write(&transactions, &self.data.accounts, &self.data.categories, &dialog.csv)
+            }
+            ExportFormat::Ledger => journal::export(&PersistedState::from(&self.data), journal::Syntax::Ledger),
+            ExportFormat::Beancount => journal::export(&PersistedState::from(&self.data), journal::Syntax::Beancount),
+        };
+        let text = match text {
+            Ok(text) => text,
+            Err(err) => {
+                self.status = err;
+                return;
+            }
+        };
+        match std::fs::write(&path, text) {
+            Ok(()) => {
+                self.status = format!("Exported {} to {}.", dialog.format.label(), path.display());
+                self.export = None;
+            }
+            Err(err) => self.status = format!("Could not write {}: {}", path.display(), err),
+        }
+    }
+
+    /// Runs the three-way merge between the open budget and the files named
+    /// in the merge window.
+    fn compare_for_merge(&mut self) {
+        let Some(dialog) = &mut self.merge_dialog else {
+            return;
+        };
+        let key = self.store.key();
+        let base = storage::load_file(std::path::Path::new(dialog.base.trim()), key);
+        let theirs = storage::load_file(std::path::Path::new(dialog.theirs.trim()), key);
+        match (base, theirs) {
+            (Ok(base), Ok(theirs)) => {
+                dialog.merge = Some(merge::three_way(&base, &PersistedState::from(&self.data), &theirs));
+            }
+            (Err(err), _) | (_, Err(err)) => self.status = err,
+        }
+    }
+
+    fn merge_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.merge_dialog else {
+            return;
+        };
+        let mut open = true;
+        let mut compare = false;
+        let mut apply = false;
+        egui::Window::new("Merge budgets")
+            .id(egui::Id::new("merge_dialog"))
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(520.0)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                if dialog.from_disk {
+                    ui.label("You and another program both changed the budget file. Pick a version for each conflict.");
+                } else {
+                    ui.label("Combines the open budget (mine) with another copy (theirs) that started from the same file.");
+                    egui::Grid::new("merge_files").num_columns(2).show(ui, |ui| {
+                        ui.label("Common ancestor");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut dialog.base)
+                                .hint_text("The copy both started from")
+                                .desired_width(340.0),
+                        );
+                        ui.end_row();
+                        ui.label("Their copy");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut dialog.theirs)
+                                .hint_text("The other person's file")
+                                .desired_width(340.0),
+                        );
+                        ui.end_row();
+                    });
+                    compare = ui.button("Compare").clicked();
+                }
+                let Some(merge) = &mut dialog.merge else {
+                    return;
+                };
+                ui.separator();
+                if merge.conflicts.is_empty() {
+                    ui.label("Everything merges cleanly.");
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} conflict(s).", merge.conflicts.len()));
+                        if ui.button("Keep all mine").clicked() {
+                            merge.choose_all(Side::Mine);
+                        }
+                        if ui.button("Keep all theirs").clicked() {
+                            merge.choose_all(Side::Theirs);
+                        }
+                    });
+                }
+                egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                    for (index, conflict) in merge.conflicts.iter_mut().enumerate() {
+                        ui.add_space(6.0);
+                        ui.label(RichText::new(conflict.title()).strong());
+                        egui::Grid::new(("merge_conflict", index)).num_columns(3).show(ui, |ui| {
+                            for (field, mine, theirs) in conflict.differences() {
+                                ui.label(RichText::new(field).color(Color32::from_rgb(150, 155, 165)));
+                                ui.label(format!("mine: {}", mine));
+                                ui.label(format!("theirs: {}", theirs));
+                                ui.end_row();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut conflict.choice, Side::Mine, "Keep mine");
+                            ui.radio_value(&mut conflict.choice, Side::Theirs, "Keep theirs");
+                        });
+                    }
+                });
+                ui.add_space(8.0);
+                apply = ui
+                    .add(
+                        egui::Button::new(RichText::new("Apply merge").color(Color32::WHITE))
+                            .fill(Color32::from_rgb(92, 106, 255)),
+                    )
+                    .clicked();
+            });
+        if compare {
+            self.compare_for_merge();
+        } else if apply {
+            if let Some(dialog) = self.merge_dialog.take()
+                && let Some(merge) = &dialog.merge
+            {
+                self.apply_merge(merge, dialog.from_disk);
+            }
+        } else if !open {
+            self.merge_dialog = None;
+        }
+    }
+
+    /// Writes the version in memory over the one on disk.
+    fn overwrite_disk(&mut self) {
+        self.disk_changed = false;
+        self.save();
+        if self.save_error.is_none() {
+            self.status = "Saved your version over the one on disk.".to_string();
+        }
+    }
+
+    fn disk_changed_banner(&mut self, ui: &mut egui::Ui) {
+        if !self.disk_changed {
+            return;
+        }
+        let mut action = None;
+        egui::Frame::none()
+            .fill(Color32::from_rgb(70, 56, 24))
+            .rounding(egui::Rounding::same(10.0))
+            .inner_margin(egui::Margin::symmetric(12.0, 8.0))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new("⚠ Another program changed the budget file. Nothing is saved until you choose.")
+                            .color(Color32::from_rgb(255, 225, 170)),
+                    );
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Keep mine").on_hover_text("Overwrite the file on disk").clicked() {
+                            action = Some(DiskAction::Overwrite);
+                        }
+                        if ui
+                            .button("Merge")
+                            .on_hover_text("Apply your changes on top of the version on disk")
+                            .clicked()
+                        {
+                            action = Some(DiskAction::Merge);
+                        }
+                        if ui.button("Reload").on_hover_text("Discard your changes and load the file").clicked() {
+                            action = Some(DiskAction::Reload);
+                        }
+                    });
+                });
+            });
+        ui.add_space(8.0);
+        match action {
+            Some(DiskAction::Reload) => self.reload_from_disk(),
+            Some(DiskAction::Merge) => self.merge_with_disk(),
+            Some(DiskAction::Overwrite) => self.overwrite_disk(),
+            None => {}
+        }
+    }
+
+    /// Leaves recovery with `state` as the new budget and saves it.
+    fn finish_recovery(&mut self, mut state: PersistedState, status: String) {
+        state.normalize();
+        self.data = state.into();
+        self.history = History::default();
+        self.recovery = None;
+        self.after_history_step();
+        self.select_period(Period::current());
+        self.synced = Some(PersistedState::from(&self.data));
+        self.catch_up_on_launch();
+        self.save();
+        self.status = status;
+    }
+
+    fn recover_from_backup(&mut self, path: PathBuf) {
+        match storage::read(&path, self.store.key()) {
+            Ok(Some((state, _))) => {
+                self.finish_recovery(state, format!("Restored from {}.", path.display()));
+            }
+            Ok(None) => self.status = format!("{} does not exist.", path.display()),
+            Err(err) => self.status = format!("That backup could not be read either: {}", err),
+        }
+    }
+
+    fn recover_partially(&mut self) {
+        match storage::read_lenient(&self.data_path, self.store.key()) {
+            Ok((state, skipped)) => {
+                let status = format!(
+                    "Recovered {} transaction(s), {} account(s) and {} categories; {} damaged part(s) were skipped.",
+                    state.transactions.len(),
+                    state.accounts.len(),
+                    state.categories.len(),
+                    skipped
+                );
+                self.finish_recovery(state, status);
+            }
+            Err(err) => self.status = err,
+        }
+    }
+
+    fn start_fresh(&mut self) {
+        let state = PersistedState::from(&BudgetData::default());
+        self.finish_recovery(state, "Started a new, empty budget.".to_string());
+    }
+
+    fn recovery_screen(&mut self, ui: &mut egui::Ui) {
+        let data_path = self.data_path.clone();
+        let Some(recovery) = &mut self.recovery else {
+            return;
+        };
+        let mut action = None;
+        ui.label(
+            RichText::new("Your budget file could not be opened")
+                .color(Color32::from_rgb(230, 232, 240))
+                .size(20.0)
+                .strong(),
+        );
+        ui.add_space(10.0);
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                ui.label(RichText::new(&recovery.error).color(Color32::from_rgb(235, 120, 120)));
+                ui.add_space(6.0);
+                let copy = match &recovery.corrupt_copy {
+                    Ok(path) => format!("The original was kept as {}.", path.display()),
+                    Err(err) => format!("The original could not be copied aside ({}). It has not been changed.", err),
+                };
+                ui.label(RichText::new(copy).color(Color32::from_rgb(150, 155, 165)));
+                ui.label(
+                    RichText::new("Nothing will be saved until you choose one of the options below.")
+                        .color(Color32::from_rgb(150, 155, 165)),
+                );
+            });
+
+        ui.add_space(12.0);
+        ui.label(RichText::new("Open a backup").strong());
+        for candidate in storage::backup_candidates(&data_path) {
+            if ui.button(candidate.display().to_string()).clicked() {
+                action = Some(RecoveryAction::Backup(candidate));
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut recovery.backup_path)
+                    .hint_text("Path to a backup file")
+                    .desired_width(320.0),
+            );
+            if ui.button("Open").clicked() && !recovery.backup_path.trim().is_empty() {
+                action = Some(RecoveryAction::Backup(PathBuf::from(recovery.backup_path.trim())));
+            }
+        });
+
+        ui.add_space(12.0);
+        ui.label(RichText::new("Recover what can be read").strong());
+        ui.label(
+            RichText::new("Keeps every transaction, account and category that is still intact and skips the rest.")
+                .color(Color32::from_rgb(150, 155, 165)),
+        );
+        if ui.button("Attempt partial recovery").clicked() {
+            action = Some(RecoveryAction::Partial);
+        }
+
+        ui.add_space(12.0);
+        ui.label(RichText::new("Start over").strong());
+        if ui
+            .add(
+                egui::Button::new(RichText::new("Start a new, empty budget").color(Color32::WHITE))
+                    .fill(Color32::from_rgb(200, 60, 80)),
+            )
+            .clicked()
+        {
+            action = Some(RecoveryAction::Fresh);
+        }
+
+        match action {
+            Some(RecoveryAction::Backup(path)) => self.recover_from_backup(path),
+            Some(RecoveryAction::Partial) => self.recover_partially(),
+            Some(RecoveryAction::Fresh) => self.start_fresh(),
+            None => {}
+        }
+    }
+
+    /// Opens the transaction form, pre-filled from `id` when editing.
+    fn open_tx_form(&mut self, id: Option<Uuid>) {
+        match id.and_then(|id| self.data.transactions.iter().find(|t| t.id == id)) {
+            Some(tx) => {
+                self.form_title = tx.title.clone();
+                self.form_amount = tx.amount.to_string();
+                self.form_date = date::format_date(tx.date, tx.time);
+                self.form_account = tx.account;
+                self.form_category = tx.category;
+                self.form_splits = tx
+                    .splits
+                    .iter()
+                    .map(|split| SplitForm {
+                        category: split.category,
+                        amount: split.amount.to_string(),
+                        memo: split.memo.clone(),
+                    })
+                    .collect();
+                self.form_transfer = tx.is_transfer();
+                if let Some(to) = tx.transfer_to {
+                    self.form_transfer_to = to;
+                }
+                self.editing_tx = Some(tx.id);
+            }
+            None => {
+                self.form_title.clear();
+                self.form_amount.clear();
+                self.form_date = "Today".to_string();
+                self.form_splits.clear();
+                self.form_transfer = false;
+                self.editing_tx = None;
+            }
+        }
+        self.show_new_tx = true;
+    }
+
+    fn save_tx_form(&mut self) {
+        if self.form_title.trim().is_empty() {
+            self.status = "Enter a title for the transaction.".to_string();
+            return;
+        }
+        let currency = Self::account_currency(&self.data.accounts, self.form_account);
+        let parsed_amount = Money::parse(&self.form_amount, currency);
+        let parsed_date = date::parse_date(&self.form_date, date::today());
+        let (amount, (tx_date, tx_time)) = match (parsed_amount, parsed_date) {
+            (Err(err), _) | (_, Err(err)) => {
+                self.status = err;
+                return;
+            }
+            (Ok(amount), Ok(when)) => (amount, when),
+        };
+        let (splits, transfer_to) = if self.form_transfer {
+            if !amount.is_positive() {
+                self.status = "Enter the amount to move as a positive number.".to_string();
+                return;
+            }
+            if self.form_transfer_to == self.form_account {
+                self.status = "Pick two different accounts for a transfer.".to_string();
+                return;
+            }
+            if Self::account_currency(&self.data.accounts, self.form_transfer_to) != currency {
+                self.status = "Transfers need two accounts in the same currency.".to_string();
+                return;
+            }
+            (Vec::new(), Some(self.form_transfer_to))
+        } else {
+            match self.parse_form_splits(amount) {
+                Ok(splits) => (splits, None),
+                Err(err) => {
+                    self.status = err;
+                    return;
+                }
+            }
+        };
+
+        let existing = self
+            .editing_tx
+            .and_then(|id| self.data.transactions.iter().find(|t| t.id == id))
+            .cloned();
+        let tx = Transaction {
+            id: self.editing_tx.unwrap_or_else(Uuid::new_v4),
+            title: self.form_title.trim().to_string(),
+            date: tx_date,
+            time: tx_time,
+            amount,
+            category: if splits.is_empty() && transfer_to.is_none() { self.form_category } else { None },
+            account: self.form_account,
+            recurring: existing.as_ref().and_then(|t| t.recurring),
+            splits,
+            transfer_to,
+            import_id: existing.as_ref().and_then(|t| t.import_id.clone()),
+        };
+        match existing {
+            Some(before) => {
+                self.execute(Command::EditTransaction { before, after: tx });
+                self.status = "Transaction updated.".to_string();
+            }
+            None => {
+                self.execute(Command::AddTransaction(tx));
+                self.status = "Transaction saved.".to_string();
+            }
+        }
+        self.form_title.clear();
+        self.form_amount.clear();
+        self.form_date = "Today".to_string();
+        self.form_splits.clear();
+        self.editing_tx = None;
+        self.show_new_tx = false;
+    }
+
+    /// Parses the split lines of the form and checks they add up to `total`.
+    fn parse_form_splits(&self, total: Money) -> Result<Vec<Split>, String> {
+        let mut splits = Vec::new();
+        for (index, line) in self.form_splits.iter().enumerate() {
+            let amount = Money::parse(&line.amount, total.currency())
+                .map_err(|err| format!("Split line {}: {}", index + 1, err))?;
+            splits.push(Split {
+                category: line.category,
+                amount,
+                memo: line.memo.trim().to_string(),
+            });
+        }
+        let allocated: Money = splits.iter().map(|s| s.amount).sum();
+        if !splits.is_empty() && allocated != total {
+            return Err(format!(
+                "Split lines add up to {:#} but the transaction is {:#}.",
+                allocated, total
+            ));
+        }
+        Ok(splits)
+    }
+
+    /// Split line editor: category, amount and memo per line, plus what is
+    /// left to allocate against the transaction amount.
+    fn split_editor(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        for (index, line) in self.form_splits.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let id_source = format!("split_category_{}", index);
+                Self::category_picker(ui, &id_source, &self.data.categories, &mut line.category);
+                ui.add(
+                    egui::TextEdit::singleline(&mut line.amount)
+                        .hint_text("Amount")
+                        .desired_width(80.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut line.memo)
+                        .hint_text("Memo")
+                        .desired_width(90.0),
+                );
+                if ui.small_button("✕").on_hover_text("Remove line").clicked() {
+                    remove = Some(index);
+                }
+            });
+        }
+        if let Some(index) = remove {
+            self.form_splits.remove(index);
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add split line").clicked() {
+                self.form_splits.push(SplitForm {
+                    category: self.form_category,
+                    ..SplitForm::default()
+                });
+            }
+            if self.form_splits.is_empty() {
+                return;
+            }
+            let currency = Self::account_currency(&self.data.accounts, self.form_account);
+            let total = Money::parse(&self.form_amount, currency).ok();
+            let allocated = self
+                .form_splits
+                .iter()
+                .map(|line| Money::parse(&line.amount, currency))
+                .sum::<Result<Money, String>>();
+            if let (Some(total), Ok(allocated)) = (total, allocated) {
+                let left = total - allocated;
+                let color = if left.is_zero() {
+                    Color32::from_rgb(120, 200, 140)
+                } else {
+                    Color32::from_rgb(235, 150, 90)
+                };
+                ui.label(RichText::new(format!("Left to allocate: {:#}", left)).color(color));
+            }
+        });
+    }
+
+    fn delete_transaction(&mut self, id: Uuid) {
+        let Some(index) = self.data.transactions.iter().position(|t| t.id == id) else {
+            return;
+        };
+        let tx = self.data.transactions[index].clone();
+        self.execute(Command::DeleteTransaction { tx, index });
+        if self.editing_tx == Some(id) {
+            self.editing_tx = None;
+            self.show_new_tx = false;
+        }
+        self.status = "Transaction deleted. Press Ctrl+Z to undo.".to_string();
+    }
+
+    fn new_tx_form(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_new_tx;
+        let editing = self.editing_tx.is_some();
+        egui::Window::new(if editing { "Edit transaction" } else { "New transaction" })
+            .id(egui::Id::new("tx_form"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.set_width(360.0);
+                ui.vertical(|ui| {
+                    let heading = if editing {
+                        "Change this transaction"
+                    } else {
+                        "Add a new transaction"
+                    };
+                    ui.label(RichText::new(heading).strong());
+                    ui.add_space(8.0);
+                    ui.label("Title");
+                    ui.text_edit_singleline(&mut self.form_title);
+                    ui.label("Date (Today, Yesterday, YYYY-MM-DD or Mon DD, YYYY; time optional)");
+                    ui.text_edit_singleline(&mut self.form_date);
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.form_transfer, false, "Income / expense");
+                        ui.selectable_value(&mut self.form_transfer, true, "Transfer");
+                    });
+                    if self.form_transfer {
+                        ui.label("Amount to move");
+                        ui.text_edit_singleline(&mut self.form_amount);
+                        ui.label("From account");
+                        Self::account_picker(ui, "form_account", &self.data.accounts, &mut self.form_account);
+                        ui.label("To account");
+                        Self::account_picker(ui, "form_transfer_to", &self.data.accounts, &mut self.form_transfer_to);
+                    } else {
+                        ui.label("Amount (use negative for expense, positive for income)");
+                        ui.text_edit_singleline(&mut self.form_amount);
+                        ui.label("Account");
+                        Self::account_picker(ui, "form_account", &self.data.accounts, &mut self.form_account);
+                        if self.form_splits.is_empty() {
+                            ui.label("Category");
+                            Self::category_picker(ui, "form_category", &self.data.categories, &mut self.form_category);
+                        } else {
+                            ui.label("Split lines (category, amount, memo)");
+                        }
+                        self.split_editor(ui);
+                    }
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add(
+                                egui::Button::new(RichText::new("Save").color(Color32::WHITE))
+                                    .fill(Color32::from_rgb(92, 106, 255)),
+                            )
+                            .clicked()
+                        {
+                            self.save_tx_form();
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.show_new_tx = false;
+                        }
+                        if let Some(id) = self.editing_tx {
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                if ui
+                                    .add(
+                                        egui::Button::new(RichText::new("Delete").color(Color32::WHITE))
+                                            .fill(Color32::from_rgb(200, 60, 80)),
+                                    )
+                                    .clicked()
+                                {
+                                    self.confirm_delete_tx = Some(id);
+                                }
+                            });
+                        }
+                    });
+                });
+            });
+        // Save and Cancel close the form from inside the window.
+        self.show_new_tx &= open;
+        if !self.show_new_tx {
+            self.editing_tx = None;
+        }
+    }
+
+    fn confirm_delete_dialog(&mut self, ctx: &egui::Context) {
+        let Some(id) = self.confirm_delete_tx else {
+            return;
+        };
+        let Some(tx) = self.data.transactions.iter().find(|t| t.id == id) else {
+            self.confirm_delete_tx = None;
+            return;
+        };
+        let summary = format!(
+            "{} · {} · {:+#}",
+            tx.title,
+            date::format_date(tx.date, tx.time),
+            tx.amount
+        );
+        let mut decision = None;
+        egui::Window::new("Delete transaction?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label(summary);
+                ui.label(
+                    RichText::new("You can bring it back with Undo (Ctrl+Z).")
+                        .color(Color32::from_rgb(150, 155, 165))
+                        .size(12.0),
+                );
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui
+                        .add(
+                            egui::Button::new(RichText::new("Delete").color(Color32::WHITE))
+                                .fill(Color32::from_rgb(200, 60, 80)),
+                        )
+                        .clicked()
+                    {
+                        decision = Some(true);
+                    }
+                    if ui.button("Keep").clicked() {
+                        decision = Some(false);
+                    }
+                });
+            });
+        match decision {
+            Some(true) => {
+                self.confirm_delete_tx = None;
+                self.delete_transaction(id);
+            }
+            Some(false) => self.confirm_delete_tx = None,
+            None => {}
+        }
+    }
+
+    /// Lists applied changes (newest first) above undone ones; clicking an
+    /// entry undoes or redoes everything up to it.
+    fn history_window(&mut self, ctx: &egui::Context) {
+        let done = self.history.done_labels();
+        let undone = self.history.undone_labels();
+        let mut undo_steps = 0;
+        let mut redo_steps = 0;
+        let mut open = self.show_history;
+        egui::Window::new("History")
+            .open(&mut open)
+            .default_width(260.0)
+            .show(ctx, |ui| {
+                if done.is_empty() && undone.is_empty() {
+                    ui.label(RichText::new("No changes yet.").color(Color32::from_rgb(150, 155, 165)));
+                }
+                egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                    for (i, label) in undone.iter().enumerate().rev() {
+                        let text = RichText::new(label).color(Color32::from_rgb(120, 124, 135)).italics();
+                        if ui.selectable_label(false, text).on_hover_text("Redo up to here").clicked() {
+                            redo_steps = i + 1;
+                        }
+                    }
+                    for (i, label) in done.iter().rev().enumerate() {
+                        if ui
+                            .selectable_label(i == 0, label)
+                            .on_hover_text("Undo back to here")
+                            .clicked()
+                        {
+                            undo_steps = i;
+                        }
+                    }
+                });
+            });
+        self.show_history = open;
+        for _ in 0..undo_steps {
+            self.undo();
+        }
+        for _ in 0..redo_steps {
+            self.redo();
+        }
+    }
+
+    fn home_page(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("Dashboard")
+                    .color(Color32::from_rgb(230, 232, 240))
+                    .size(20.0)
+                    .strong(),
+            );
+            ui.add_space(12.0);
+            let mut selected = self.period;
+            if ui.button("◀").on_hover_text("Previous month").clicked() {
+                selected = selected.prev();
+            }
+            ui.label(
+                RichText::new(self.period.label())
+                    .color(Color32::from_rgb(220, 225, 235))
+                    .strong(),
+            );
+            if ui.button("▶").on_hover_text("Next month").clicked() {
+                selected = selected.next();
+            }
+            if self.period != Period::current() && ui.button("This month").clicked() {
+                selected = Period::current();
+            }
+            if selected != self.period {
+                self.select_period(selected);
+            }
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui
+                    .add(
+                        egui::Button::new(
+                            RichText::new("New transaction")
+                                .color(Color32::WHITE)
+                                .strong(),
+                        )
+                        .fill(Color32::from_rgb(92, 106, 255))
+                        .rounding(egui::Rounding::same(10.0)),
+                    )
+                    .clicked()
+                {
+                    self.open_tx_form(None);
+                }
+            });
+        });
+
+        ui.add_space(10.0);
+        ui.horizontal_wrapped(|ui| {
+            let card_size = egui::vec2(220.0, 100.0);
+            for total in self.total_balances() {
+                let title = format!("All Account ({})", total.currency());
+                ui.allocate_ui_with_layout(card_size, egui::Layout::top_down(egui::Align::LEFT), |ui| {
+                    Self::stat_card(ui, &title, total, Color32::from_rgb(127, 138, 255));
+                });
+            }
+            for (i, account) in self.data.accounts.iter().enumerate() {
+                let accent = ACCOUNT_ACCENTS[i % ACCOUNT_ACCENTS.len()];
+                let title = format!("{} ({})", account.name, account.opening_balance.currency());
+                let balance = model::account_balance(account, &self.data.transactions);
+                ui.allocate_ui_with_layout(card_size, egui::Layout::top_down(egui::Align::LEFT), |ui| {
+                    Self::stat_card(ui, &title, balance, accent);
+                });
+            }
+        });
+
+        ui.add_space(8.0);
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label(
+                            RichText::new("Monthly budget")
+                                .color(Color32::from_rgb(160, 165, 175)),
+                        );
+                        let budget = self.data.budget.amount_for(self.period);
+                        ui.label(
+                            RichText::new(format!("{} {:#}", budget.currency(), budget))
+                                .color(Color32::from_rgb(220, 225, 235))
+                                .size(18.0)
+                                .strong(),
+                        );
+                    });
+                    if self.data.budget.rollover_from.is_some_and(|from| from < self.period) {
+                        ui.add_space(12.0);
+                        let carried = self.data.budget.carried_into(self.period, &self.data.transactions);
+                        Self::figure(ui, "Carried over", carried, Self::signed_color(carried));
+                    }
+                    ui.add_space(12.0);
+                    ui.vertical(|ui| {
+                        ui.label(RichText::new("Remaining").color(Color32::from_rgb(160, 165, 175)));
+                        let remaining = self.remaining();
+                        ui.label(
+                            RichText::new(format!("{} {:#}", remaining.currency(), remaining))
+                                .color(Self::signed_color(remaining))
+                                .strong(),
+                        );
+                    });
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Budget").color(Color32::from_rgb(180, 185, 195)));
+                            self.budget_currency_picker(ui);
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.budget_input)
+                                    .desired_width(100.0),
+                            );
+                            if ui.button("Update").clicked() {
+                                self.update_budget_from_input();
+                            }
+                        });
+                    });
+                });
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    let net = self.net_cashflow();
+                    Self::figure(ui, "Income", self.total_income(), Color32::from_rgb(110, 220, 140));
+                    ui.add_space(12.0);
+                    Self::figure(ui, "Expenses", self.total_expenses(), Color32::from_rgb(240, 80, 100));
+                    ui.add_space(12.0);
+                    Self::figure(ui, "Net cashflow", net, Self::signed_color(net));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        let mut rollover = self.data.budget.rollover_from.is_some();
+                        if ui
+                            .checkbox(&mut rollover, "Roll over unspent budget")
+                            .on_hover_text("Carry each month's leftover or overspend into the next, starting with the selected month.")
+                            .changed()
+                        {
+                            self.set_rollover(rollover);
+                        }
+                    });
+                });
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Add entry").color(Color32::from_rgb(180, 185, 195)));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.entry_description)
+                            .hint_text("Description")
+                            .desired_width(200.0),
+                    );
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.entry_amount)
+                            .hint_text("Amount")
+                            .desired_width(100.0),
+                    );
+                    Self::account_picker(ui, "entry_account", &self.data.accounts, &mut self.entry_account);
+                    Self::category_picker(ui, "entry_category", &self.data.categories, &mut self.entry_category);
+                    if ui
+                        .add(
+                            egui::Button::new(
+                                RichText::new("Add")
+                                    .color(Color32::WHITE)
+                                    .strong(),
+                            )
+                            .fill(Color32::from_rgb(92, 106, 255))
+                            .rounding(egui::Rounding::same(8.0)),
+                        )
+                        .clicked()
+                    {
+                        self.add_entry();
+                    }
+                });
+            });
+
+        let upcoming = self.upcoming_recurring(30, 5);
+        if !upcoming.is_empty() {
+            ui.add_space(12.0);
+            ui.label(
+                RichText::new("Upcoming")
+                    .color(Color32::from_rgb(220, 225, 235))
+                    .size(16.0)
+                    .strong(),
+            );
+            for (due, rule) in upcoming {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(date::format_date(due, None))
+                            .color(Color32::from_rgb(150, 155, 165))
+                            .size(13.0),
+                    );
+                    ui.label(RichText::new(&rule.title).color(Color32::from_rgb(220, 225, 235)));
+                    ui.label(
+                        RichText::new(format!("{:+#}", rule.amount))
+                            .color(Self::signed_color(rule.amount))
+                            .strong(),
+                    );
+                });
+            }
+        }
+
+        ui.add_space(12.0);
+        ui.label(
+            RichText::new(format!("Transactions in {}:", self.period.label()))
+                .color(Color32::from_rgb(220, 225, 235))
+                .size(16.0)
+                .strong(),
+        );
+        ui.add_space(6.0);
+        self.tx_filter_bar(ui);
+        ui.add_space(6.0);
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .max_height(320.0)
+            .show(ui, |ui| {
+                for tx in self.transactions_newest_first() {
+                    let category = tx.category.and_then(|id| self.category(id));
+                    let account = match tx.transfer_to {
+                        Some(to) => format!("{} → {}", self.account_name(tx.account), self.account_name(to)),
+                        None => self.account_name(tx.account).to_string(),
+                    };
+                    if Self::transaction_row(ui, tx, &account, category) {
+                        clicked = Some(tx.id);
+                    }
+                    ui.add_space(6.0);
+                }
+            });
+        if let Some(id) = clicked {
+            self.open_tx_form(Some(id));
+        }
+    }
+
+    /// Search, account and category filters above the transaction list.
+    fn tx_filter_bar(&mut self, ui: &mut egui::Ui) {
+        let filter = &mut self.tx_filter;
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut filter.search)
+                    .hint_text("Search")
+                    .desired_width(160.0),
+            );
+            let accounts = &self.data.accounts;
+            let account = filter
+                .account
+                .and_then(|id| accounts.iter().find(|a| a.id == id))
+                .map_or("All accounts", |a| a.name.as_str());
+            egui::ComboBox::from_id_source("tx_filter_account")
+                .selected_text(account)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.account, None, "All accounts");
+                    for account in accounts {
+                        ui.selectable_value(&mut filter.account, Some(account.id), &account.name);
+                    }
+                });
+            let categories = &self.data.categories;
+            let category = filter
+                .category
+                .map_or_else(|| "All categories".to_string(), |id| model::category_path(categories, id));
+            egui::ComboBox::from_id_source("tx_filter_category")
+                .selected_text(category)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.category, None, "All categories");
+                    for category in categories {
+                        let label = format!("{} {}", category.icon, model::category_path(categories, category.id));
+                        ui.selectable_value(&mut filter.category, Some(category.id), label.trim());
+                    }
+                });
+            if filter.is_active() && ui.button("Clear").clicked() {
+                *filter = TxFilter::default();
+            }
+        });
+    }
+
+    fn save_account_from_form(&mut self) {
+        let name = self.account_form_name.trim().to_string();
+        if name.is_empty() {
+            self.status = "Enter a name for the account.".to_string();
+            return;
+        }
+        let existing = self
+            .editing_account
+            .and_then(|id| self.data.accounts.iter().find(|a| a.id == id))
+            .cloned();
+        // A new account takes its currency from the opening balance ("100 USD");
+        // an existing one keeps the currency its transactions are in.
+        let currency = existing.as_ref().map(|a| a.opening_balance.currency());
+        let opening = if self.account_form_opening.trim().is_empty() {
+            Money::zero(currency.unwrap_or_default())
+        } else {
+            let parsed = match currency {
+                Some(currency) => Money::parse(&self.account_form_opening, currency),
+                None => self.account_form_opening.parse(),
+            };
+            match parsed {
+                Ok(val) => val,
+                Err(err) => {
+                    self.status = err;
+                    return;
+                }
+            }
+        };
+
+        match existing {
+            Some(before) => {
+                let after = Account {
+                    name,
+                    kind: self.account_form_kind,
+                    opening_balance: opening,
+                    ..before.clone()
+                };
+                self.execute(Command::EditAccount { before, after });
+                self.status = "Account updated.".to_string();
+            }
+            None => {
+                self.execute(Command::AddAccount(Account::new(&name, self.account_form_kind, opening)));
+                self.status = "Account added.".to_string();
+            }
+        }
+        self.clear_account_form();
+    }
+
+    fn edit_account(&mut self, id: Uuid) {
+        if let Some(account) = self.data.accounts.iter().find(|a| a.id == id) {
+            self.account_form_name = account.name.clone();
+            self.account_form_kind = account.kind;
+            self.account_form_opening = account.opening_balance.to_string();
+            self.editing_account = Some(id);
+        }
+    }
+
+    fn clear_account_form(&mut self) {
+        self.account_form_name.clear();
+        self.account_form_kind = AccountKind::Cash;
+        self.account_form_opening.clear();
+        self.editing_account = None;
+    }
+
+    /// Only accounts without transactions can be removed, and at least one
+    /// account must remain so new entries always have somewhere to go.
+    fn delete_account(&mut self, id: Uuid) {
+        if self.data.accounts.len() <= 1 {
+            self.status = "Keep at least one account.".to_string();
+            return;
+        }
+        if self
+            .data
+            .transactions
+            .iter()
+            .any(|t| t.account == id || t.transfer_to == Some(id))
+        {
+            self.status = "Move or remove this account's transactions before deleting it.".to_string();
+            return;
+        }
+        if self.data.recurring.iter().any(|r| r.account == id) {
+            self.status = "Move or remove this account's recurring transactions before deleting it.".to_string();
+            return;
+        }
+        let Some(index) = self.data.accounts.iter().position(|a| a.id == id) else {
+            return;
+        };
+        let account = self.data.accounts[index].clone();
+        self.execute(Command::DeleteAccount { account, index });
+        self.after_history_step();
+        self.status = "Account deleted.".to_string();
+    }
+
+    /// The bank's last reported balance next to ours on the same day.
+    fn reconciliation(ui: &mut egui::Ui, account: &Account, transactions: &[Transaction]) {
+        let Some(statement) = account.statement_balance else {
+            ui.label("");
+            return;
+        };
+        let difference = model::account_balance_on(account, transactions, statement.date) - statement.amount;
+        ui.vertical(|ui| {
+            ui.label(format!("{:+#} on {}", statement.amount, date::format_date(statement.date, None)));
+            if difference.is_zero() {
+                ui.label(RichText::new("Reconciled").color(Color32::from_rgb(120, 200, 140)).size(12.0));
+            } else {
+                ui.label(
+                    RichText::new(format!("Off by {:+#}", difference))
+                        .color(Color32::from_rgb(235, 120, 120))
+                        .size(12.0),
+                );
+            }
+        });
+    }
+
+    fn accounts_page(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            RichText::new("Accounts")
+                .color(Color32::from_rgb(230, 232, 240))
+                .size(20.0)
+                .strong(),
+        );
+        ui.add_space(10.0);
+
+        let mut edit = None;
+        let mut delete = None;
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                egui::Grid::new("accounts_grid")
+                    .num_columns(6)
+                    .spacing(egui::vec2(18.0, 10.0))
+                    .show(ui, |ui| {
+                        for header in ["Name", "Type", "Opening", "Balance", "Bank statement", ""] {
+                            ui.label(RichText::new(header).color(Color32::from_rgb(160, 165, 175)));
+                        }
+                        ui.end_row();
+                        for account in &self.data.accounts {
+                            let balance = model::account_balance(account, &self.data.transactions);
+                            ui.label(RichText::new(&account.name).color(Color32::from_rgb(220, 225, 235)).strong());
+                            ui.label(account.kind.label());
+                            let currency = account.opening_balance.currency();
+                            ui.label(format!("{} {:#}", currency, account.opening_balance));
+                            ui.label(
+                                RichText::new(format!("{} {:+#}", currency, balance))
+                                    .color(Self::signed_color(balance))
+                                    .strong(),
+                            );
+                            Self::reconciliation(ui, account, &self.data.transactions);
+                            ui.horizontal(|ui| {
+                                if ui.button("Edit").clicked() {
+                                    edit = Some(account.id);
+                                }
+                                if ui.button("Delete").clicked() {
+                                    delete = Some(account.id);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                        ui.label(RichText::new("All accounts").color(Color32::from_rgb(160, 165, 175)));
+                        ui.label("");
+                        ui.label("");
+                        let totals: Vec<String> = self
+                            .total_balances()
+                            .iter()
+                            .map(|total| format!("{} {:+#}", total.currency(), total))
+                            .collect();
+                        ui.label(RichText::new(totals.join("\n")).strong());
+                        ui.label("");
+                        ui.end_row();
+                    });
+            });
+        if let Some(id) = edit {
+            self.edit_account(id);
+        }
+        if let Some(id) = delete {
+            self.delete_account(id);
+        }
+
+        ui.add_space(12.0);
+        let heading = if self.editing_account.is_some() {
+            "Edit account"
+        } else {
+            "New account"
+        };
+        ui.label(
+            RichText::new(heading)
+                .color(Color32::from_rgb(220, 225, 235))
+                .size(16.0)
+                .strong(),
+        );
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.account_form_name)
+                    .hint_text("Name")
+                    .desired_width(180.0),
+            );
+            egui::ComboBox::from_id_source("account_form_kind")
+                .selected_text(self.account_form_kind.label())
+                .show_ui(ui, |ui| {
+                    for kind in AccountKind::ALL {
+                        ui.selectable_value(&mut self.account_form_kind, kind, kind.label());
+                    }
+                });
+            ui.add(
+                egui::TextEdit::singleline(&mut self.account_form_opening)
+                    .hint_text("Opening balance")
+                    .desired_width(120.0),
+            );
+            if ui
+                .add(
+                    egui::Button::new(RichText::new("Save").color(Color32::WHITE).strong())
+                        .fill(Color32::from_rgb(92, 106, 255))
+                        .rounding(egui::Rounding::same(8.0)),
+                )
+                .clicked()
+            {
+                self.save_account_from_form();
+            }
+            if self.editing_account.is_some() && ui.button("Cancel").clicked() {
+                self.clear_account_form();
+            }
+        });
+        ui.label(
+            RichText::new("Credit cards use a negative opening balance for the amount owed.")
+                .color(Color32::from_rgb(150, 155, 165))
+                .size(12.0),
+        );
+    }
+
+    fn save_category_from_form(&mut self) {
+        let name = self.category_form_name.trim().to_string();
+        if name.is_empty() {
+            self.status = "Enter a name for the category.".to_string();
+            return;
+        }
+        let icon = self.category_form_icon.trim().to_string();
+        let parent = self.category_form_parent;
+
+        match self.editing_category {
+            Some(id) => {
+                if parent.is_some_and(|p| p == id || model::is_descendant(&self.data.categories, p, id)) {
+                    self.status = "A category cannot be nested inside itself.".to_string();
+                    return;
+                }
+                if let Some(before) = self.category(id).cloned() {
+                    let after = Category {
+                        name,
+                        color: self.category_form_color,
+                        icon,
+                        parent,
+                        ..before.clone()
+                    };
+                    self.execute(Command::EditCategory { before, after });
+                }
+                self.status = "Category updated.".to_string();
+            }
+            None => {
+                let category = Category::new(&name, self.category_form_color, &icon, parent);
+                self.execute(Command::AddCategory(category));
+                self.status = "Category added.".to_string();
+            }
+        }
+        self.clear_category_form();
+    }
+
+    fn edit_category(&mut self, id: Uuid) {
+        if let Some(category) = self.category(id).cloned() {
+            self.category_form_name = category.name;
+            self.category_form_color = category.color;
+            self.category_form_icon = category.icon;
+            self.category_form_parent = category.parent;
+            self.editing_category = Some(id);
+        }
+    }
+
+    fn clear_category_form(&mut self) {
+        self.category_form_name.clear();
+        self.category_form_color = DEFAULT_CATEGORY_COLOR;
+        self.category_form_icon.clear();
+        self.category_form_parent = None;
+        self.editing_category = None;
+    }
+
+    fn set_category_archived(&mut self, id: Uuid, archived: bool) {
+        let Some(before) = self.category(id).cloned() else {
+            return;
+        };
+        let after = Category {
+            archived,
+            ..before.clone()
+        };
+        self.execute(Command::EditCategory { before, after });
+        self.after_history_step();
+        self.status = if archived {
+            "Category archived.".to_string()
+        } else {
+            "Category restored.".to_string()
+        };
+    }
+
+    /// Moves every transaction and sub-category of `source` onto `target`,
+    /// then removes `source`.
+    fn merge_categories(&mut self, source: Uuid, target: Uuid) {
+        if source == target {
+            self.status = "Pick two different categories to merge.".to_string();
+            return;
+        }
+        let Some(index) = self.data.categories.iter().position(|c| c.id == source) else {
+            return;
+        };
+        let removed = self.data.categories[index].clone();
+        let mut commands = Vec::new();
+        for category in &self.data.categories {
+            if category.parent != Some(source) {
+                continue;
+            }
+            let parent = if category.id == target { removed.parent } else { Some(target) };
+            commands.push(Command::EditCategory {
+                before: category.clone(),
+                after: Category {
+                    parent,
+                    ..category.clone()
+                },
+            });
+        }
+        let mut moved = 0;
+        for tx in self.data.transactions.iter().filter(|t| t.uses_category(source)) {
+            commands.push(Command::EditTransaction {
+                before: tx.clone(),
+                after: tx.with_category_replaced(source, Some(target)),
+            });
+            moved += 1;
+        }
+        for rule in self.data.recurring.iter().filter(|r| r.category == Some(source)) {
+            commands.push(Command::EditRule {
+                before: rule.clone(),
+                after: RecurringRule {
+                    category: Some(target),
+                    ..rule.clone()
+                },
+            });
+        }
+        let target_name = self.category(target).map(|c| c.name.clone()).unwrap_or_default();
+        commands.push(Command::DeleteCategory {
+            category: removed.clone(),
+            index,
+        });
+        self.execute(Command::Batch {
+            label: format!("Merge \"{}\" into \"{}\"", removed.name, target_name),
+            commands,
+        });
+        for selected in [
+            &mut self.entry_category,
+            &mut self.form_category,
+            &mut self.category_form_parent,
+            &mut self.rule_form_category,
+        ] {
+            if *selected == Some(source) {
+                *selected = Some(target);
+            }
+        }
+        if self.editing_category == Some(source) {
+            self.clear_category_form();
+        }
+        self.merge_source = None;
+        self.status = format!("Categories merged; {} transaction(s) moved.", moved);
+    }
+
+    fn categories_page(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("Categories")
+                    .color(Color32::from_rgb(230, 232, 240))
+                    .size(20.0)
+                    .strong(),
+            );
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.checkbox(&mut self.show_archived_categories, "Show archived");
+            });
+        });
+        ui.add_space(10.0);
+
+        let mut rows: Vec<(String, &Category)> = self
+            .data
+            .categories
+            .iter()
+            .filter(|c| self.show_archived_categories || !c.archived)
+            .map(|c| (model::category_path(&self.data.categories, c.id), c))
+            .collect();
+        rows.sort_by_key(|(path, _)| path.to_lowercase());
+
+        let mut edit = None;
+        let mut archive = None;
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    egui::Grid::new("categories_grid")
+                        .num_columns(4)
+                        .spacing(egui::vec2(18.0, 10.0))
+                        .show(ui, |ui| {
+                            for header in ["Category", "Transactions", "Status", ""] {
+                                ui.label(RichText::new(header).color(Color32::from_rgb(160, 165, 175)));
+                            }
+                            ui.end_row();
+                            for (path, category) in &rows {
+                                ui.horizontal(|ui| {
+                                    ui.label(RichText::new("●").color(category.color).size(18.0));
+                                    ui.label(
+                                        RichText::new(format!("{} {}", category.icon, path).trim())
+                                            .color(Color32::from_rgb(220, 225, 235))
+                                            .strong(),
+                                    );
+                                });
+                                let count = self
+                                    .data
+                                    .transactions
+                                    .iter()
+                                    .flat_map(Transaction::lines)
+                                    .filter(|(c, _)| *c == Some(category.id))
+                                    .count();
+                                ui.label(count.to_string());
+                                ui.label(if category.archived { "Archived" } else { "Active" });
+                                ui.horizontal(|ui| {
+                                    if ui.button("Edit").clicked() {
+                                        edit = Some(category.id);
+                                    }
+                                    let label = if category.archived { "Restore" } else { "Archive" };
+                                    if ui.button(label).clicked() {
+                                        archive = Some((category.id, !category.archived));
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+        if let Some(id) = edit {
+            self.edit_category(id);
+        }
+        if let Some((id, archived)) = archive {
+            self.set_category_archived(id, archived);
+        }
+
+        ui.add_space(12.0);
+        let heading = if self.editing_category.is_some() {
+            "Edit category"
+        } else {
+            "New category"
+        };
+        ui.label(
+            RichText::new(heading)
+                .color(Color32::from_rgb(220, 225, 235))
+                .size(16.0)
+                .strong(),
+        );
+        // Parents exclude the category being edited and its descendants.
+        let parent_options: Vec<Category> = self
+            .data
+            .categories
+            .iter()
+            .filter(|c| !c.archived)
+            .filter(|c| {
+                self.editing_category
+                    .is_none_or(|id| c.id != id && !model::is_descendant(&self.data.categories, c.id, id))
+            })
+            .cloned()
+            .collect();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.category_form_name)
+                    .hint_text("Name")
+                    .desired_width(160.0),
+            );
+            ui.add(
+                egui::TextEdit::singleline(&mut self.category_form_icon)
+                    .hint_text("Icon")
+                    .desired_width(40.0),
+            );
+            ui.color_edit_button_srgba(&mut self.category_form_color);
+            ui.label("Parent");
+            egui::ComboBox::from_id_source("category_form_parent")
+                .selected_text(
+                    self.category_form_parent
+                        .map(|id| model::category_path(&self.data.categories, id))
+                        .unwrap_or_else(|| "None".to_string()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.category_form_parent, None, "None");
+                    for category in &parent_options {
+                        let label = model::category_path(&self.data.categories, category.id);
+                        ui.selectable_value(&mut self.category_form_parent, Some(category.id), label);
+                    }
+                });
+            if ui
+                .add(
+                    egui::Button::new(RichText::new("Save").color(Color32::WHITE).strong())
+                        .fill(Color32::from_rgb(92, 106, 255))
+                        .rounding(egui::Rounding::same(8.0)),
+                )
+                .clicked()
+            {
+                self.save_category_from_form();
+            }
+            if self.editing_category.is_some() && ui.button("Cancel").clicked() {
+                self.clear_category_form();
+            }
+        });
+
+        ui.add_space(12.0);
+        ui.label(
+            RichText::new("Merge categories")
+                .color(Color32::from_rgb(220, 225, 235))
+                .size(16.0)
+                .strong(),
+        );
+        let mut merge = None;
+        ui.horizontal(|ui| {
+            Self::category_picker(ui, "merge_source", &self.data.categories, &mut self.merge_source);
+            ui.label("into");
+            Self::category_picker(ui, "merge_target", &self.data.categories, &mut self.merge_target);
+            if ui.button("Merge").clicked() {
+                match (self.merge_source, self.merge_target) {
+                    (Some(source), Some(target)) => merge = Some((source, target)),
+                    _ => self.status = "Pick the category to merge and the one to keep.".to_string(),
+                }
+            }
+        });
+        if let Some((source, target)) = merge {
+            self.merge_categories(source, target);
+        }
+    }
+
+    fn save_rule_from_form(&mut self) {
+        let title = self.rule_form_title.trim().to_string();
+        if title.is_empty() {
+            self.status = "Enter a title for the recurring transaction.".to_string();
+            return;
+        }
+        let currency = Self::account_currency(&self.data.accounts, self.rule_form_account);
+        let amount = match Money::parse(&self.rule_form_amount, currency) {
+            Ok(amount) if !amount.is_zero() => amount,
+            Ok(_) => {
+                self.status = "Enter a non-zero amount (negative for expenses).".to_string();
+                return;
+            }
+            Err(err) => {
+                self.status = err;
+                return;
+            }
+        };
+        let today = date::today();
+        let start = match date::parse_date(&self.rule_form_start, today) {
+            Ok((start, _)) => start,
+            Err(err) => {
+                self.status = err;
+                return;
+            }
+        };
+        let end = if self.rule_form_end.trim().is_empty() {
+            None
+        } else {
+            match date::parse_date(&self.rule_form_end, today) {
+                Ok((end, _)) if end >= start => Some(end),
+                Ok(_) => {
+                    self.status = "The end date must be on or after the start date.".to_string();
+                    return;
+                }
+                Err(err) => {
+                    self.status = err;
+                    return;
+                }
+            }
+        };
+
+        let existing = self
+            .editing_rule
+            .and_then(|id| self.data.recurring.iter().find(|r| r.id == id))
+            .cloned();
+        let rule = RecurringRule {
+            id: existing.as_ref().map_or_else(Uuid::new_v4, |r| r.id),
+            title,
+            amount,
+            account: self.rule_form_account,
+            category: self.rule_form_category,
+            frequency: self.rule_form_frequency,
+            start,
+            end,
+            last_generated: existing.as_ref().and_then(|r| r.last_generated),
+        };
+        // Occurrences already due are generated right away, in the same undo step.
+        let (transactions, rule) = recurring::catch_up(&rule, today);
+        let generated = transactions.len();
+        let mut commands = vec![match existing {
+            Some(before) => Command::EditRule { before, after: rule },
+            None => Command::AddRule(rule),
+        }];
+        commands.extend(transactions.into_iter().map(Command::AddTransaction));
+        let command = if commands.len() == 1 {
+            commands.remove(0)
+        } else {
+            let label = commands[0].label();
+            Command::Batch { label, commands }
+        };
+        self.execute(command);
+        self.status = if generated > 0 {
+            format!("Recurring transaction saved; {} past occurrence(s) added.", generated)
+        } else {
+            "Recurring transaction saved.".to_string()
+        };
+        self.clear_rule_form();
+    }
+
+    fn edit_rule(&mut self, id: Uuid) {
+        if let Some(rule) = self.data.recurring.iter().find(|r| r.id == id) {
+            self.rule_form_title = rule.title.clone();
+            self.rule_form_amount = rule.amount.to_string();
+            self.rule_form_account = rule.account;
+            self.rule_form_category = rule.category;
+            self.rule_form_frequency = rule.frequency;
+            self.rule_form_start = date::format_date(rule.start, None);
+            self.rule_form_end = rule.end.map(|end| date::format_date(end, None)).unwrap_or_default();
+            self.editing_rule = Some(id);
+        }
+    }
+
+    fn clear_rule_form(&mut self) {
+        self.rule_form_title.clear();
+        self.rule_form_amount.clear();
+        self.rule_form_category = None;
+        self.rule_form_frequency = Frequency::Monthly;
+        self.rule_form_start = "Today".to_string();
+        self.rule_form_end.clear();
+        self.editing_rule = None;
+    }
+
+    /// Removes the schedule; transactions it already generated are kept.
+    fn delete_rule(&mut self, id: Uuid) {
+        let Some(index) = self.data.recurring.iter().position(|r| r.id == id) else {
+            return;
+        };
+        let rule = self.data.recurring[index].clone();
+        self.execute(Command::DeleteRule { rule, index });
+        if self.editing_rule == Some(id) {
+            self.clear_rule_form();
+        }
+        self.status = "Recurring transaction deleted.".to_string();
+    }
+
+    /// Income or spending per category for the selected month. Split
+    /// transactions contribute each line to its own category.
+    fn breakdown_page(&mut self, ui: &mut egui::Ui, income: bool) {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new(if income { "Income" } else { "Expenses" })
+                    .color(Color32::from_rgb(230, 232, 240))
+                    .size(20.0)
+                    .strong(),
+            );
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("▶").clicked() {
+                    self.select_period(self.period.next());
+                }
+                ui.label(self.period.label());
+                if ui.button("◀").clicked() {
+                    self.select_period(self.period.prev());
+                }
+            });
+        });
+        ui.add_space(10.0);
+
+        let totals = model::category_totals(&self.data.transactions, self.period, self.data.budget.currency, income);
+        let grand_total: Money = totals.iter().map(|(_, total)| *total).sum();
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                if totals.is_empty() {
+                    let empty = if income { "No income this month." } else { "No spending this month." };
+                    ui.label(RichText::new(empty).color(Color32::from_rgb(150, 155, 165)));
+                    return;
+                }
+                egui::Grid::new("breakdown_grid")
+                    .num_columns(3)
+                    .spacing(egui::vec2(18.0, 10.0))
+                    .show(ui, |ui| {
+                        for header in ["Category", "Amount", "Share"] {
+                            ui.label(RichText::new(header).color(Color32::from_rgb(160, 165, 175)));
+                        }
+                        ui.end_row();
+                        for (category, total) in &totals {
+                            let category = category.and_then(|id| self.category(id));
+                            ui.horizontal(|ui| {
+                                let color = category.map_or(model::UNCATEGORIZED_COLOR, |c| c.color);
+                                ui.label(RichText::new("●").color(color).size(18.0));
+                                let name = category.map_or_else(
+                                    || "Uncategorized".to_string(),
+                                    |c| format!("{} {}", c.icon, model::category_path(&self.data.categories, c.id)),
+                                );
+                                ui.label(RichText::new(name.trim()).color(Color32::from_rgb(220, 225, 235)));
+                            });
+                            ui.label(format!("{:#}", total));
+                            let share = total.cents() as f64 / grand_total.cents().max(1) as f64;
+                            ui.add(egui::ProgressBar::new(share as f32).desired_width(160.0).show_percentage());
+                            ui.end_row();
+                        }
+                        ui.label(RichText::new("Total").strong());
+                        ui.label(RichText::new(format!("{:#}", grand_total)).strong());
+                        ui.end_row();
+                    });
+            });
+    }
+
+    fn save_error_banner(&mut self, ui: &mut egui::Ui) {
+        let Some(error) = &self.save_error else {
+            return;
+        };
+        let mut retry = false;
+        egui::Frame::none()
+            .fill(Color32::from_rgb(70, 28, 34))
+            .rounding(egui::Rounding::same(10.0))
+            .inner_margin(egui::Margin::symmetric(12.0, 8.0))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(format!("⚠ {}", error)).color(Color32::from_rgb(255, 190, 190)));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        retry = ui.button("Retry").clicked();
+                    });
+                });
+            });
+        ui.add_space(8.0);
+        if retry {
+            self.save();
+            if self.save_error.is_none() {
+                self.status = "Saved.".to_string();
+            }
+        }
+    }
+
+    fn options_page(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            RichText::new("Options")
+                .color(Color32::from_rgb(230, 232, 240))
+                .size(20.0)
+                .strong(),
+        );
+        ui.add_space(10.0);
+        let path = self.data_path.clone();
+        let backups = storage::backups(&path);
+        let mut changed = false;
+        let mut data_file = None;
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
             .show(ui, |ui| {
+                ui.label(RichText::new("Backups").color(Color32::from_rgb(220, 225, 235)).strong());
+                ui.horizontal(|ui| {
+                    ui.label("Keep the newest");
+                    changed = ui
+                        .add(egui::DragValue::new(&mut self.settings.backup_retention).clamp_range(0..=100))
+                        .changed();
+                    ui.label("backups (0 turns backups off)");
+                });
                 ui.label(
-                    RichText::new(title)
-                        .color(Color32::from_rgb(190, 195, 210))
-                        .size(13.0),
+                    RichText::new(format!(
+                        "A copy is taken at most once an hour while you edit, in {}.",
+                        storage::backups_dir(&path).display()
+                    ))
+                    .color(Color32::from_rgb(150, 155, 165))
+                    .size(12.0),
                 );
                 ui.add_space(6.0);
-                ui.horizontal(|ui| {
-                    ui.label(RichText::new("PHP").color(Color32::from_rgb(190, 195, 210)));
+                if backups.is_empty() {
+                    ui.label(RichText::new("No backups yet.").color(Color32::from_rgb(150, 155, 165)));
+                }
+                for backup in &backups {
+                    let name = backup.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+                    ui.label(RichText::new(name).color(Color32::from_rgb(200, 205, 215)).size(13.0));
+                }
+            });
+
+        ui.add_space(12.0);
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                ui.label(RichText::new("Data file").color(Color32::from_rgb(220, 225, 235)).strong());
+                ui.label(format!("In use: {}", path.display()));
+                if self.data_path_from_args {
                     ui.label(
-                        RichText::new(format!("{:+.2}", amount))
-                            .color(accent)
-                            .size(20.0)
-                            .strong(),
+                        RichText::new("Chosen with --data-file, which takes precedence over this setting.")
+                            .color(Color32::from_rgb(235, 150, 90))
+                            .size(12.0),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.data_file_input)
+                            .hint_text(paths::default_data_file().display().to_string())
+                            .desired_width(320.0),
                     );
+                    if ui.button("Use this file").clicked() && !self.data_file_input.trim().is_empty() {
+                        data_file = Some(PathBuf::from(self.data_file_input.trim()));
+                    }
+                    if ui.button("Use default location").clicked() {
+                        data_file = Some(paths::default_data_file());
+                    }
                 });
+                ui.label(
+                    RichText::new("Switches to that file now and opens it again on the next launch.")
+                        .color(Color32::from_rgb(150, 155, 165))
+                        .size(12.0),
+                );
             });
-    }
 
-    fn transaction_row(ui: &mut egui::Ui, tx: &Transaction) {
+        ui.add_space(12.0);
+        let mut convert = false;
         egui::Frame::none()
-            .fill(Color32::from_rgb(32, 32, 40))
-            .rounding(egui::Rounding::same(10.0))
-            .inner_margin(egui::Margin::symmetric(12.0, 10.0))
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                ui.label(RichText::new("Storage format").color(Color32::from_rgb(220, 225, 235)).strong());
+                let sqlite = storage::is_sqlite(&path);
+                ui.label(if sqlite {
+                    "SQLite database: each edit writes only the records it changed."
+                } else {
+                    "JSON file: every edit rewrites the whole file."
+                });
+                let label = if sqlite { "Convert to JSON" } else { "Convert to SQLite" };
+                convert = ui.button(label).clicked();
+                ui.label(
+                    RichText::new("Writes a copy next to the current file and switches to it; the original is kept.")
+                        .color(Color32::from_rgb(150, 155, 165))
+                        .size(12.0),
+                );
+            });
+
+        ui.add_space(12.0);
+        let mut passphrase = None;
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
             .show(ui, |ui| {
+                ui.label(RichText::new("Passphrase").color(Color32::from_rgb(220, 225, 235)).strong());
+                let encrypted = self.store.key().is_some();
+                if storage::is_sqlite(&path) {
+                    ui.label("Only JSON budgets can be encrypted; convert this one to JSON first.");
+                    return;
+                }
+                ui.label(if encrypted {
+                    "This budget is encrypted and asks for its passphrase when opened."
+                } else {
+                    "This budget is stored unencrypted. Set a passphrase to encrypt it."
+                });
                 ui.horizontal(|ui| {
-                    ui.label(
-                        RichText::new("●")
-                            .color(tx.color)
-                            .size(22.0)
-                            .family(egui::FontFamily::Monospace),
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.passphrase_input)
+                            .password(true)
+                            .hint_text("New passphrase")
+                            .desired_width(180.0),
                     );
-                    ui.vertical(|ui| {
-                        ui.label(
-                            RichText::new(tx.title.to_owned())
-                                .color(Color32::from_rgb(220, 225, 235))
-                                .strong(),
-                        );
-                        ui.label(
-                            RichText::new(tx.date.to_owned())
-                                .color(Color32::from_rgb(150, 155, 165))
-                                .size(12.0),
-                        );
-                    });
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        let color = if tx.amount < 0.0 {
-                            Color32::from_rgb(240, 80, 100)
-                        } else {
-                            Color32::from_rgb(110, 220, 140)
-                        };
-                        ui.label(
-                            RichText::new(format!("{:+.2}", tx.amount))
-                                .color(color)
-                                .strong(),
-                        );
-                    });
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.passphrase_confirm)
+                            .password(true)
+                            .hint_text("Repeat it")
+                            .desired_width(180.0),
+                    );
+                    let label = if encrypted { "Change passphrase" } else { "Set passphrase" };
+                    if ui.button(label).clicked() {
+                        passphrase = Some(Some(self.passphrase_input.clone()));
+                    }
                 });
+                if encrypted && ui.button("Remove passphrase").clicked() {
+                    passphrase = Some(None);
+                }
+                ui.label(
+                    RichText::new(
+                        "A forgotten passphrase cannot be recovered. Backups keep the protection they were made with.",
+                    )
+                    .color(Color32::from_rgb(150, 155, 165))
+                    .size(12.0),
+                );
             });
-    }
 
-    fn data_path() -> PathBuf {
-        PathBuf::from("budget_data.json")
+        if let Some(data_file) = data_file {
+            self.open_budget(data_file);
+        }
+        if let Some(passphrase) = passphrase {
+            self.change_passphrase(passphrase);
+        }
+        if convert {
+            self.convert_budget();
+        }
+        if changed {
+            self.store.set_backup_retention(self.settings.backup_retention);
+            if let Err(err) = self.settings.save(&Settings::path()) {
+                self.status = err;
+            }
+        }
     }
 
-    fn save_state(&self) -> Result<(), String> {
-        let state = PersistedState {
-            monthly_budget: self.monthly_budget,
-            transactions: self.transactions.clone(),
+    /// Sets, changes or (with `None`) removes the passphrase and rewrites the
+    /// budget file straight away.
+    fn change_passphrase(&mut self, passphrase: Option<String>) {
+        // Saving waits while the file has outside changes, so the new
+        // passphrase would not reach the disk yet.
+        if self.disk_changed {
+            self.status =
+                "Another program changed the budget file; keep your version or reload before changing the passphrase."
+                    .to_string();
+            return;
+        }
+        if let Some(passphrase) = &passphrase {
+            if passphrase.is_empty() {
+                self.status = "Enter a passphrase.".to_string();
+                return;
+            }
+            if *passphrase != self.passphrase_confirm {
+                self.status = "The two passphrases do not match.".to_string();
+                return;
+            }
+        }
+        if let Err(err) = self.store.set_passphrase(passphrase.as_deref()) {
+            self.status = err;
+            return;
+        }
+        self.passphrase_input.clear();
+        self.passphrase_confirm.clear();
+        self.save();
+        self.status = match (&self.save_error, passphrase) {
+            (Some(err), _) => err.clone(),
+            (None, Some(_)) => "The budget is now encrypted with the new passphrase.".to_string(),
+            (None, None) => "The passphrase was removed; the budget is stored unencrypted.".to_string(),
         };
-        serde_json::to_string_pretty(&state)
-            .map_err(|e| e.to_string())
-            .and_then(|json| fs::write(Self::data_path(), json).map_err(|e| e.to_string()))
     }
 
-    fn load_state(&self) -> Option<PersistedState> {
-        fs::read_to_string(Self::data_path())
-            .ok()
-            .and_then(|contents| serde_json::from_str(&contents).ok())
+    /// Copies the open budget into the other storage format and switches to
+    /// the copy. A profile pointing at the old file follows it.
+    fn convert_budget(&mut self) {
+        if self.save_error.is_some() {
+            self.status = "The current budget has unsaved changes; fix the save error before converting.".to_string();
+            return;
+        }
+        let extension = if storage::is_sqlite(&self.data_path) { "json" } else { "sqlite" };
+        let target = self.data_path.with_extension(extension);
+        if let Err(err) = storage::convert(&self.data_path, &target) {
+            self.status = err;
+            return;
+        }
+        let previous = self.data_path.clone();
+        for profile in self.settings.profiles.iter_mut().filter(|p| p.data_file == previous) {
+            profile.data_file = target.clone();
+        }
+        self.open_budget(target.clone());
+        if self.data_path == target {
+            self.status = format!("Converted to {}.", target.display());
+        }
     }
 
-    fn new_tx_form(&mut self, ctx: &egui::Context) {
-        let mut open = self.show_new_tx;
-        egui::Window::new("New transaction")
-            .open(&mut open)
-            .collapsible(false)
-            .resizable(false)
-            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
-            .show(ctx, |ui| {
-                ui.set_width(360.0);
-                ui.vertical(|ui| {
-                    ui.label(RichText::new("Add a new transaction").strong());
-                    ui.add_space(8.0);
-                    ui.label("Title");
-                    ui.text_edit_singleline(&mut self.form_title);
-                    ui.label("Date");
-                    ui.text_edit_singleline(&mut self.form_date);
-                    ui.label("Amount (use negative for expense, positive for income)");
-                    ui.text_edit_singleline(&mut self.form_amount);
-                    ui.add_space(8.0);
-                    ui.horizontal(|ui| {
-                        if ui
-                            .add(
-                                egui::Button::new(RichText::new("Save").color(Color32::WHITE))
-                                    .fill(Color32::from_rgb(92, 106, 255)),
-                            )
-                            .clicked()
-                        {
-                            let parsed_amount = self.form_amount.trim().parse::<f64>();
-                            if self.form_title.trim().is_empty() {
-                                self.status = "Enter a title for the transaction.".to_string();
-                            } else if let Ok(amount) = parsed_amount {
-                                self.transactions.push(Transaction {
-                                    title: self.form_title.trim().to_string(),
-                                    date: self.form_date.trim().to_string(),
-                                    amount,
-                                    color: if amount < 0.0 {
-                                        Color32::from_rgb(230, 78, 95)
-                                    } else {
-                                        Color32::from_rgb(110, 220, 140)
-                                    },
-                                });
-                                self.form_title.clear();
-                                self.form_amount.clear();
-                                self.form_date = "Today".to_string();
-                                self.status = "Transaction saved.".to_string();
-                                let _ = self.save_state();
-                                self.show_new_tx = false;
-                            } else {
-                                self.status = "Enter a valid number for amount.".to_string();
-                            }
+    fn recurring_page(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            RichText::new("Recurring")
+                .color(Color32::from_rgb(230, 232, 240))
+                .size(20.0)
+                .strong(),
+        );
+        ui.add_space(10.0);
+
+        let mut edit = None;
+        let mut delete = None;
+        egui::Frame::none()
+            .fill(Color32::from_rgb(25, 25, 33))
+            .rounding(egui::Rounding::same(12.0))
+            .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
+            .inner_margin(egui::Margin::same(14.0))
+            .show(ui, |ui| {
+                if self.data.recurring.is_empty() {
+                    ui.label(
+                        RichText::new("No recurring transactions yet. Add rent, subscriptions or salary below.")
+                            .color(Color32::from_rgb(150, 155, 165)),
+                    );
+                    return;
+                }
+                egui::Grid::new("recurring_grid")
+                    .num_columns(6)
+                    .spacing(egui::vec2(18.0, 10.0))
+                    .show(ui, |ui| {
+                        for header in ["Title", "Amount", "Schedule", "Account", "Next", ""] {
+                            ui.label(RichText::new(header).color(Color32::from_rgb(160, 165, 175)));
                         }
-                        if ui.button("Cancel").clicked() {
-                            self.show_new_tx = false;
+                        ui.end_row();
+                        for rule in &self.data.recurring {
+                            ui.label(RichText::new(&rule.title).color(Color32::from_rgb(220, 225, 235)).strong());
+                            ui.label(
+                                RichText::new(format!("{} {:+#}", rule.amount.currency(), rule.amount))
+                                    .color(Self::signed_color(rule.amount))
+                                    .strong(),
+                            );
+                            let schedule = match rule.end {
+                                Some(end) => format!("{} until {}", rule.frequency.label(), date::format_date(end, None)),
+                                None => rule.frequency.label().to_string(),
+                            };
+                            ui.label(schedule);
+                            ui.label(self.account_name(rule.account));
+                            ui.label(
+                                rule.next_due()
+                                    .map(|due| date::format_date(due, None))
+                                    .unwrap_or_else(|| "Ended".to_string()),
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.button("Edit").clicked() {
+                                    edit = Some(rule.id);
+                                }
+                                if ui.button("Delete").clicked() {
+                                    delete = Some(rule.id);
+                                }
+                            });
+                            ui.end_row();
                         }
                     });
-                });
             });
-        self.show_new_tx = open;
+        if let Some(id) = edit {
+            self.edit_rule(id);
+        }
+        if let Some(id) = delete {
+            self.delete_rule(id);
+        }
+
+        ui.add_space(12.0);
+        let heading = if self.editing_rule.is_some() {
+            "Edit recurring transaction"
+        } else {
+            "New recurring transaction"
+        };
+        ui.label(
+            RichText::new(heading)
+                .color(Color32::from_rgb(220, 225, 235))
+                .size(16.0)
+                .strong(),
+        );
+        egui::Grid::new("rule_form")
+            .num_columns(2)
+            .spacing(egui::vec2(12.0, 8.0))
+            .show(ui, |ui| {
+                ui.label("Title");
+                ui.add(egui::TextEdit::singleline(&mut self.rule_form_title).desired_width(220.0));
+                ui.end_row();
+                ui.label("Amount");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.rule_form_amount)
+                        .hint_text("negative for expenses")
+                        .desired_width(220.0),
+                );
+                ui.end_row();
+                ui.label("Repeats");
+                egui::ComboBox::from_id_source("rule_form_frequency")
+                    .selected_text(self.rule_form_frequency.label())
+                    .show_ui(ui, |ui| {
+                        for frequency in Frequency::ALL {
+                            ui.selectable_value(&mut self.rule_form_frequency, frequency, frequency.label());
+                        }
+                    });
+                ui.end_row();
+                ui.label("Starts");
+                ui.add(egui::TextEdit::singleline(&mut self.rule_form_start).desired_width(220.0));
+                ui.end_row();
+                ui.label("Ends");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.rule_form_end)
+                        .hint_text("optional")
+                        .desired_width(220.0),
+                );
+                ui.end_row();
+                ui.label("Account");
+                Self::account_picker(ui, "rule_form_account", &self.data.accounts, &mut self.rule_form_account);
+                ui.end_row();
+                ui.label("Category");
+                Self::category_picker(ui, "rule_form_category", &self.data.categories, &mut self.rule_form_category);
+                ui.end_row();
+            });
+        ui.horizontal(|ui| {
+            if ui
+                .add(
+                    egui::Button::new(RichText::new("Save").color(Color32::WHITE).strong())
+                        .fill(Color32::from_rgb(92, 106, 255))
+                        .rounding(egui::Rounding::same(8.0)),
+                )
+                .clicked()
+            {
+                self.save_rule_from_form();
+            }
+            if self.editing_rule.is_some() && ui.button("Cancel").clicked() {
+                self.clear_rule_form();
+            }
+        });
     }
 }
 
@@ -359,6 +4266,38 @@ fn app_icon() -> Option<Arc<egui::IconData>> {
 impl eframe::App for BudgetApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.apply_style(ctx);
+        if self.unlock.is_some() {
+            egui::CentralPanel::default()
+                .frame(
+                    egui::Frame::none()
+                        .fill(Color32::from_rgb(18, 18, 24))
+                        .inner_margin(egui::Margin::symmetric(16.0, 14.0)),
+                )
+                .show(ctx, |ui| self.unlock_screen(ui));
+            return;
+        }
+        if self.recovery.is_some() {
+            egui::CentralPanel::default()
+                .frame(
+                    egui::Frame::none()
+                        .fill(Color32::from_rgb(18, 18, 24))
+                        .inner_margin(egui::Margin::symmetric(16.0, 14.0)),
+                )
+                .show(ctx, |ui| {
+                    self.recovery_screen(ui);
+                    if !self.status.is_empty() {
+                        ui.add_space(8.0);
+                        ui.label(
+                            RichText::new(&self.status)
+                                .color(Color32::from_rgb(140, 180, 255))
+                                .size(13.0),
+                        );
+                    }
+                });
+            return;
+        }
+        self.check_disk(ctx);
+        self.handle_shortcuts(ctx);
 
         egui::SidePanel::left("nav")
             .exact_width(190.0)
@@ -371,12 +4310,23 @@ impl eframe::App for BudgetApp {
                             .size(18.0)
                             .strong(),
                     );
+                    self.budget_switcher(ui);
+                    self.file_menu(ui);
                     ui.add_space(16.0);
                 });
 
-                Self::nav_button(ui, "Home", true);
-                Self::nav_button(ui, "Accounts", false);
-                Self::nav_button(ui, "Categories", false);
+                if Self::nav_button(ui, "Home", self.page == Page::Home) {
+                    self.page = Page::Home;
+                }
+                if Self::nav_button(ui, "Accounts", self.page == Page::Accounts) {
+                    self.page = Page::Accounts;
+                }
+                if Self::nav_button(ui, "Categories", self.page == Page::Categories) {
+                    self.page = Page::Categories;
+                }
+                if Self::nav_button(ui, "Recurring", self.page == Page::Recurring) {
+                    self.page = Page::Recurring;
+                }
 
                 ui.add_space(12.0);
                 ui.separator();
@@ -387,12 +4337,37 @@ impl eframe::App for BudgetApp {
                         .size(13.0),
                 );
                 Self::nav_button(ui, "Cashflow", false);
-                Self::nav_button(ui, "Expenses", false);
-                Self::nav_button(ui, "Income", false);
+                if Self::nav_button(ui, "Expenses", self.page == Page::Expenses) {
+                    self.page = Page::Expenses;
+                }
+                if Self::nav_button(ui, "Income", self.page == Page::Income) {
+                    self.page = Page::Income;
+                }
 
                 ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                     ui.add_space(12.0);
-                    Self::nav_button(ui, "Options", false);
+                    if Self::nav_button(ui, "Options", self.page == Page::Options) {
+                        self.page = Page::Options;
+                    }
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(self.history.can_undo(), egui::Button::new("↶"))
+                            .on_hover_text("Undo (Ctrl+Z)")
+                            .clicked()
+                        {
+                            self.undo();
+                        }
+                        if ui
+                            .add_enabled(self.history.can_redo(), egui::Button::new("↷"))
+                            .on_hover_text("Redo (Ctrl+Shift+Z)")
+                            .clicked()
+                        {
+                            self.redo();
+                        }
+                        if ui.selectable_label(self.show_history, "History").clicked() {
+                            self.show_history = !self.show_history;
+                        }
+                    });
                 });
             });
 
@@ -403,137 +4378,17 @@ impl eframe::App for BudgetApp {
                     .inner_margin(egui::Margin::symmetric(16.0, 14.0)),
             )
             .show(ctx, |ui| {
-                ui.horizontal(|ui| {
-                    ui.label(
-                        RichText::new("Dashboard")
-                            .color(Color32::from_rgb(230, 232, 240))
-                            .size(20.0)
-                            .strong(),
-                    );
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        if ui
-                            .add(
-                                egui::Button::new(
-                                    RichText::new("New transaction")
-                                        .color(Color32::WHITE)
-                                        .strong(),
-                                )
-                                .fill(Color32::from_rgb(92, 106, 255))
-                                .rounding(egui::Rounding::same(10.0)),
-                            )
-                            .clicked()
-                        {
-                            self.show_new_tx = true;
-                        }
-                    });
-                });
-
-                ui.add_space(10.0);
-                ui.horizontal(|ui| {
-                    ui.allocate_ui_with_layout(
-                        egui::vec2(ui.available_width() * 0.5 - 6.0, 100.0),
-                        egui::Layout::top_down(egui::Align::LEFT),
-                        |ui| {
-                            Self::stat_card(ui, "All Account (PHP)", -self.total_spent(), Color32::from_rgb(127, 138, 255));
-                        },
-                    );
-                    ui.allocate_ui_with_layout(
-                        egui::vec2(ui.available_width(), 100.0),
-                        egui::Layout::top_down(egui::Align::LEFT),
-                        |ui| {
-                            Self::stat_card(ui, "Bank (PHP)", -self.total_spent(), Color32::from_rgb(120, 255, 205));
-                        },
-                    );
-                });
-
-                ui.add_space(8.0);
-                egui::Frame::none()
-                    .fill(Color32::from_rgb(25, 25, 33))
-                    .rounding(egui::Rounding::same(12.0))
-                    .stroke(egui::Stroke::new(1.0, Color32::from_rgb(45, 45, 55)))
-                    .inner_margin(egui::Margin::same(14.0))
-                    .show(ui, |ui| {
-                        ui.horizontal(|ui| {
-                            ui.vertical(|ui| {
-                                ui.label(
-                                    RichText::new("Monthly budget")
-                                        .color(Color32::from_rgb(160, 165, 175)),
-                                );
-                                ui.label(
-                                    RichText::new(format!("PHP {:.2}", self.monthly_budget))
-                                        .color(Color32::from_rgb(220, 225, 235))
-                                        .size(18.0)
-                                        .strong(),
-                                );
-                            });
-                            ui.add_space(12.0);
-                            ui.vertical(|ui| {
-                                ui.label(RichText::new("Remaining").color(Color32::from_rgb(160, 165, 175)));
-                                ui.label(
-                                    RichText::new(format!("PHP {:.2}", self.remaining()))
-                                        .color(Color32::from_rgb(110, 220, 140))
-                                        .strong(),
-                                );
-                            });
-                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                                ui.horizontal(|ui| {
-                                    ui.label(RichText::new("Budget (PHP)").color(Color32::from_rgb(180, 185, 195)));
-                                    ui.add(
-                                        egui::TextEdit::singleline(&mut self.budget_input)
-                                            .desired_width(100.0),
-                                    );
-                                    if ui.button("Update").clicked() {
-                                        self.update_budget_from_input();
-                                    }
-                                });
-                            });
-                        });
-                        ui.add_space(6.0);
-                        ui.horizontal(|ui| {
-                            ui.label(RichText::new("Add entry").color(Color32::from_rgb(180, 185, 195)));
-                            ui.add(
-                                egui::TextEdit::singleline(&mut self.entry_description)
-                                    .hint_text("Description")
-                                    .desired_width(200.0),
-                            );
-                            ui.add(
-                                egui::TextEdit::singleline(&mut self.entry_amount)
-                                    .hint_text("Amount")
-                                    .desired_width(100.0),
-                            );
-                            if ui
-                                .add(
-                                    egui::Button::new(
-                                        RichText::new("Add")
-                                            .color(Color32::WHITE)
-                                            .strong(),
-                                    )
-                                    .fill(Color32::from_rgb(92, 106, 255))
-                                    .rounding(egui::Rounding::same(8.0)),
-                                )
-                                .clicked()
-                            {
-                                self.add_entry();
-                            }
-                        });
-                    });
-
-                ui.add_space(12.0);
-                ui.label(
-                    RichText::new("Recent transactions:")
-                        .color(Color32::from_rgb(220, 225, 235))
-                        .size(16.0)
-                        .strong(),
-                );
-                ui.add_space(6.0);
-                egui::ScrollArea::vertical()
-                    .max_height(320.0)
-                    .show(ui, |ui| {
-                        for tx in self.transactions.iter().rev() {
-                            Self::transaction_row(ui, tx);
-                            ui.add_space(6.0);
-                        }
-                    });
+                self.disk_changed_banner(ui);
+                self.save_error_banner(ui);
+                match self.page {
+                    Page::Home => self.home_page(ui),
+                    Page::Accounts => self.accounts_page(ui),
+                    Page::Categories => self.categories_page(ui),
+                    Page::Recurring => self.recurring_page(ui),
+                    Page::Expenses => self.breakdown_page(ui, false),
+                    Page::Income => self.breakdown_page(ui, true),
+                    Page::Options => self.options_page(ui),
+                }
 
                 if !self.status.is_empty() {
                     ui.add_space(8.0);
@@ -548,5 +4403,15 @@ impl eframe::App for BudgetApp {
         if self.show_new_tx {
             self.new_tx_form(ctx);
         }
+        self.confirm_delete_dialog(ctx);
+        if self.show_history {
+            self.history_window(ctx);
+        }
+        self.budget_dialog_window(ctx);
+        self.merge_window(ctx);
+        self.csv_import_window(ctx);
+        self.ofx_import_window(ctx);
+        self.qif_import_window(ctx);
+        self.export_window(ctx);
     }
 }
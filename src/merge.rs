@@ -0,0 +1,320 @@
+//! Combining versions of a budget that were edited separately.
+//!
+//! A three-way merge compares "mine" and "theirs" with the `base` both
+//! started from. Records are matched by their `id` and monthly budgets by
+//! their period, so a change made on only one side is taken as it is; only
+//! something both sides changed differently becomes a `Conflict`.
+
+use crate::model::PersistedState;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Lists of records with an `id`, as named in the data file.
+const LISTS: [&str; 4] = ["accounts", "categories", "transactions", "recurring"];
+/// Single values of the budget plan.
+const FIELDS: [&str; 2] = ["monthly_budget", "rollover_from"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Mine,
+    Theirs,
+}
+
+/// Where a merged value goes in the data file.
+#[derive(Debug, Clone)]
+enum Slot {
+    Field(&'static str),
+    PeriodBudget(String),
+    Record(&'static str),
+}
+
+/// Something both sides changed differently. `None` means that side
+/// deleted it.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    slot: Slot,
+    pub mine: Option<Value>,
+    pub theirs: Option<Value>,
+    pub choice: Side,
+}
+
+impl Conflict {
+    /// What the conflict is about, e.g. `Transaction "Groceries"`.
+    pub fn title(&self) -> String {
+        match &self.slot {
+            Slot::Field("monthly_budget") => "Default monthly budget".to_string(),
+            Slot::Field("rollover_from") => "Rollover start".to_string(),
+            Slot::Field(field) => field.to_string(),
+            Slot::PeriodBudget(period) => format!("Budget for {}", period),
+            Slot::Record(list) => {
+                let kind = match *list {
+                    "accounts" => "Account",
+                    "categories" => "Category",
+                    "recurring" => "Recurring rule",
+                    _ => "Transaction",
+                };
+                let name = [&self.mine, &self.theirs]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|value| value.get("title").or_else(|| value.get("name")).and_then(Value::as_str))
+                    .unwrap_or_default();
+                format!("{} \"{}\"", kind, name)
+            }
+        }
+    }
+
+    /// The fields that differ as `(field, mine, theirs)`, or the whole
+    /// values (with an empty field name) for settings and deletions.
+    pub fn differences(&self) -> Vec<(String, String, String)> {
+        match (&self.mine, &self.theirs) {
+            (Some(Value::Object(mine)), Some(Value::Object(theirs))) => {
+                let mut fields: Vec<&String> = mine.keys().chain(theirs.keys()).collect();
+                fields.sort();
+                fields.dedup();
+                fields
+                    .into_iter()
+                    .filter(|field| mine.get(*field) != theirs.get(*field))
+                    .map(|field| (field.clone(), show(mine.get(field)), show(theirs.get(field))))
+                    .collect()
+            }
+            (mine, theirs) => vec![(String::new(), show(mine.as_ref()), show(theirs.as_ref()))],
+        }
+    }
+}
+
+fn show(value: Option<&Value>) -> String {
+    match value {
+        None => "(deleted)".to_string(),
+        Some(Value::Null) => "(none)".to_string(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Object(object)) => object
+            .get("title")
+            .or_else(|| object.get("name"))
+            .and_then(Value::as_str)
+            .unwrap_or("(kept)")
+            .to_string(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// The outcome of `three_way`: everything that merged cleanly plus the
+/// conflicts, each set to "mine" until the user picks.
+#[derive(Debug, Clone)]
+pub struct Merge {
+    /// "Theirs" as the starting point, so fields the merge ignores survive.
+    document: Map<String, Value>,
+    /// Each slot with its merged value, or the index of its conflict.
+    merged: Vec<(Slot, Result<Option<Value>, usize>)>,
+    pub conflicts: Vec<Conflict>,
+}
+
+pub fn three_way(base: &PersistedState, mine: &PersistedState, theirs: &PersistedState) -> Merge {
+    let [base, mine, theirs] = [base, mine, theirs].map(to_object);
+    let mut merge = Merge {
+        document: theirs.clone(),
+        merged: Vec::new(),
+        conflicts: Vec::new(),
+    };
+    for field in FIELDS {
+        merge.combine(Slot::Field(field), base.get(field), mine.get(field), theirs.get(field));
+    }
+    let [base_periods, mine_periods, theirs_periods] = [&base, &mine, &theirs].map(|o| entries(o, "period_budgets"));
+    let sides = [&base_periods, &mine_periods, &theirs_periods].map(|entries| index(entries));
+    for period in keys(&mine_periods, &theirs_periods, &base_periods) {
+        let [base_value, mine_value, theirs_value] = sides.each_ref().map(|side| side.get(period.as_str()).copied());
+        merge.combine(Slot::PeriodBudget(period.clone()), base_value, mine_value, theirs_value);
+    }
+    for list in LISTS {
+        let [base_records, mine_records, theirs_records] = [&base, &mine, &theirs].map(|o| records(o, list));
+        let sides = [&base_records, &mine_records, &theirs_records].map(|entries| index(entries));
+        for id in keys(&mine_records, &theirs_records, &base_records) {
+            let [base_value, mine_value, theirs_value] = sides.each_ref().map(|side| side.get(id.as_str()).copied());
+            merge.combine(Slot::Record(list), base_value, mine_value, theirs_value);
+        }
+    }
+    merge
+}
+
+impl Merge {
+    fn combine(&mut self, slot: Slot, base: Option<&Value>, mine: Option<&Value>, theirs: Option<&Value>) {
+        let value = if mine == theirs || theirs == base {
+            mine
+        } else if mine == base {
+            theirs
+        } else {
+            self.merged.push((slot.clone(), Err(self.conflicts.len())));
+            self.conflicts.push(Conflict {
+                slot,
+                mine: mine.cloned(),
+                theirs: theirs.cloned(),
+                choice: Side::Mine,
+            });
+            return;
+        };
+        self.merged.push((slot, Ok(value.cloned())));
+    }
+
+    /// Settles every conflict the same way.
+    pub fn choose_all(&mut self, side: Side) {
+        for conflict in &mut self.conflicts {
+            conflict.choice = side;
+        }
+    }
+
+    /// The merged budget with every conflict settled by its `choice`.
+    pub fn finish(&self) -> Result<PersistedState, String> {
+        let mut document = self.document.clone();
+        let mut periods = Map::new();
+        let mut lists: Vec<(&str, Vec<Value>)> = LISTS.iter().map(|list| (*list, Vec::new())).collect();
+        for (slot, value) in &self.merged {
+            let value = match value {
+                Ok(value) => value.as_ref(),
+                Err(index) => {
+                    let conflict = &self.conflicts[*index];
+                    match conflict.choice {
+                        Side::Mine => conflict.mine.as_ref(),
+                        Side::Theirs => conflict.theirs.as_ref(),
+                    }
+                }
+            };
+            match (slot, value) {
+                (Slot::Field(field), Some(value)) => {
+                    document.insert(field.to_string(), value.clone());
+                }
+                (Slot::Field(field), None) => {
+                    document.remove(*field);
+                }
+                (Slot::PeriodBudget(period), Some(value)) => {
+                    periods.insert(period.clone(), value.clone());
+                }
+                (Slot::Record(list), Some(value)) => {
+                    if let Some((_, records)) = lists.iter_mut().find(|(name, _)| name == list) {
+                        records.push(value.clone());
+                    }
+                }
+                (Slot::PeriodBudget(_) | Slot::Record(_), None) => {}
+            }
+        }
+        document.insert("period_budgets".to_string(), Value::Object(periods));
+        for (list, records) in lists {
+            document.insert(list.to_string(), Value::Array(records));
+        }
+        serde_json::from_value(Value::Object(document)).map_err(|err| format!("Could not merge: {}", err))
+    }
+}
+
+fn to_object(state: &PersistedState) -> Map<String, Value> {
+    match serde_json::to_value(state) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// `(key, value)` pairs of the map at `field`.
+fn entries(object: &Map<String, Value>, field: &str) -> Vec<(String, Value)> {
+    object
+        .get(field)
+        .and_then(Value::as_object)
+        .map(|map| map.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default()
+}
+
+/// `(id, record)` pairs of the list at `list`, in file order.
+fn records(object: &Map<String, Value>, list: &str) -> Vec<(String, Value)> {
+    object
+        .get(list)
+        .and_then(Value::as_array)
+        .map(|records| {
+            records
+                .iter()
+                .filter_map(|record| Some((record.get("id")?.as_str()?.to_string(), record.clone())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Keys of `mine` in order, then those only `theirs` has, then those both
+/// deleted (which merge to nothing).
+fn keys(mine: &[(String, Value)], theirs: &[(String, Value)], base: &[(String, Value)]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    mine.iter()
+        .chain(theirs)
+        .chain(base)
+        .filter(|(key, _)| seen.insert(key.as_str()))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// Values by key, so each side of a large budget is searched only once. The
+/// first of two entries with the same key wins.
+fn index(entries: &[(String, Value)]) -> HashMap<&str, &Value> {
+    let mut index = HashMap::with_capacity(entries.len());
+    for (key, value) in entries {
+        index.entry(key.as_str()).or_insert(value);
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use crate::schema;
+    use uuid::Uuid;
+
+    fn fixture() -> PersistedState {
+        schema::load_fixture(include_str!("../tests/fixtures/budget_data_v2.json")).0
+    }
+
+    #[test]
+    fn merges_changes_made_on_either_side() {
+        let base = fixture();
+        let mut mine = base.clone();
+        let mut theirs = base.clone();
+
+        let mut added = mine.transactions[0].clone();
+        added.id = Uuid::new_v4();
+        added.title = "Mine".to_string();
+        mine.transactions.push(added.clone());
+        mine.accounts[0].name = "Renamed here".to_string();
+
+        let mut other = theirs.transactions[0].clone();
+        other.id = Uuid::new_v4();
+        other.title = "Theirs".to_string();
+        theirs.transactions.push(other.clone());
+        let removed = theirs.transactions.remove(0);
+
+        let merge = three_way(&base, &mine, &theirs);
+        assert!(merge.conflicts.is_empty());
+        let merged = merge.finish().unwrap();
+        assert_eq!(merged.accounts[0].name, "Renamed here");
+        assert!(merged.transactions.iter().any(|t| t.id == added.id));
+        assert!(merged.transactions.iter().any(|t| t.id == other.id));
+        assert!(merged.transactions.iter().all(|t| t.id != removed.id));
+    }
+
+    #[test]
+    fn reports_what_both_sides_changed() {
+        let base = fixture();
+        let mut mine = base.clone();
+        let mut theirs = base.clone();
+        let currency = base.monthly_budget.currency();
+        mine.transactions[0].title = "Groceries".to_string();
+        theirs.transactions[0].title = "Supermarket".to_string();
+        mine.monthly_budget = Money::from_cents(100, currency);
+        theirs.monthly_budget = Money::from_cents(200, currency);
+
+        let mut merge = three_way(&base, &mine, &theirs);
+        assert_eq!(merge.conflicts.len(), 2);
+        let tx = merge.conflicts.iter_mut().find(|c| c.title().starts_with("Transaction")).unwrap();
+        assert_eq!(
+            tx.differences(),
+            vec![("title".to_string(), "Groceries".to_string(), "Supermarket".to_string())]
+        );
+        tx.choice = Side::Theirs;
+
+        let merged = merge.finish().unwrap();
+        assert_eq!(merged.transactions[0].title, "Supermarket");
+        assert_eq!(merged.monthly_budget, mine.monthly_budget);
+    }
+}
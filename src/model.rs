@@ -0,0 +1,569 @@
+use crate::date::Period;
+use crate::money::{Currency, Money};
+use crate::recurring::RecurringRule;
+use crate::schema;
+use chrono::{NaiveDate, NaiveTime};
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    /// Stable identity used for editing and deleting. Files written before
+    /// ids existed load with the nil id and get one from `PersistedState::normalize`.
+    #[serde(default)]
+    pub id: Uuid,
+    pub title: String,
+    pub date: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<NaiveTime>,
+    pub amount: Money,
+    /// Category the dot color and analytics come from; `None` is uncategorized.
+    #[serde(default)]
+    pub category: Option<Uuid>,
+    /// Account the money moved in or out of. Files written before accounts
+    /// existed load with the nil id and are assigned by `PersistedState::normalize`.
+    #[serde(default)]
+    pub account: Uuid,
+    /// Recurring rule that generated this transaction, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurring: Option<Uuid>,
+    /// Category lines of a split transaction. When present they add up to
+    /// `amount` and take the place of `category`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<Split>,
+    /// Set on transfers: `amount` (always positive) leaves `account` and
+    /// arrives in this account. Transfers are neither income nor spending.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_to: Option<Uuid>,
+    /// The bank's id (OFX FITID) of an imported transaction, so importing an
+    /// overlapping statement again skips it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub import_id: Option<String>,
+}
+
+/// One categorised part of a split transaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Split {
+    #[serde(default)]
+    pub category: Option<Uuid>,
+    pub amount: Money,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub memo: String,
+}
+
+impl Transaction {
+    pub fn is_transfer(&self) -> bool {
+        self.transfer_to.is_some()
+    }
+
+    /// How much this transaction moves the balance of `account`.
+    pub fn effect_on(&self, account: Uuid) -> Money {
+        let mut effect = Money::default();
+        if self.account == account {
+            effect += if self.is_transfer() { -self.amount } else { self.amount };
+        }
+        if self.transfer_to == Some(account) {
+            effect += self.amount;
+        }
+        effect
+    }
+
+    /// The (category, amount) lines analytics should count: each split on its
+    /// own, or the whole transaction when it is not split. Transfers have none.
+    pub fn lines(&self) -> Vec<(Option<Uuid>, Money)> {
+        if self.is_transfer() {
+            Vec::new()
+        } else if self.splits.is_empty() {
+            vec![(self.category, self.amount)]
+        } else {
+            self.splits.iter().map(|s| (s.category, s.amount)).collect()
+        }
+    }
+
+    pub fn uses_category(&self, id: Uuid) -> bool {
+        self.lines().iter().any(|(category, _)| *category == Some(id))
+    }
+
+    /// Copy with every reference to `from` (including split lines) pointing at `to`.
+    pub fn with_category_replaced(&self, from: Uuid, to: Option<Uuid>) -> Transaction {
+        let mut tx = self.clone();
+        if tx.category == Some(from) {
+            tx.category = to;
+        }
+        for split in &mut tx.splits {
+            if split.category == Some(from) {
+                split.category = to;
+            }
+        }
+        tx
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountKind {
+    Cash,
+    Bank,
+    EWallet,
+    CreditCard,
+}
+
+impl AccountKind {
+    pub const ALL: [AccountKind; 4] = [
+        AccountKind::Cash,
+        AccountKind::Bank,
+        AccountKind::EWallet,
+        AccountKind::CreditCard,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AccountKind::Cash => "Cash",
+            AccountKind::Bank => "Bank",
+            AccountKind::EWallet => "E-wallet",
+            AccountKind::CreditCard => "Credit card",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: Uuid,
+    pub name: String,
+    pub kind: AccountKind,
+    /// Balance before the first recorded transaction. Credit cards carry a
+    /// negative balance for the amount owed.
+    pub opening_balance: Money,
+    /// The balance the bank last reported, from an imported statement.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement_balance: Option<StatementBalance>,
+}
+
+/// A balance as of the end of `date`, for reconciling against the bank.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StatementBalance {
+    pub amount: Money,
+    pub date: NaiveDate,
+}
+
+impl Account {
+    pub fn new(name: &str, kind: AccountKind, opening_balance: Money) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            kind,
+            opening_balance,
+            statement_balance: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Category {
+    pub id: Uuid,
+    pub name: String,
+    pub color: Color32,
+    /// Short emoji or symbol drawn next to the name.
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub parent: Option<Uuid>,
+    /// Archived categories are hidden from pickers but still label old transactions.
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl Category {
+    pub fn new(name: &str, color: Color32, icon: &str, parent: Option<Uuid>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            color,
+            icon: icon.to_string(),
+            parent,
+            archived: false,
+        }
+    }
+}
+
+pub const UNCATEGORIZED_COLOR: Color32 = Color32::from_rgb(120, 124, 135);
+
+/// Full "Parent / Child" path of a category, guarding against parent cycles.
+pub fn category_path(categories: &[Category], id: Uuid) -> String {
+    let mut names = Vec::new();
+    let mut current = Some(id);
+    while let Some(id) = current {
+        let Some(category) = categories.iter().find(|c| c.id == id) else {
+            break;
+        };
+        if names.len() > categories.len() {
+            break;
+        }
+        names.push(category.name.as_str());
+        current = category.parent;
+    }
+    names.reverse();
+    names.join(" / ")
+}
+
+/// True if `ancestor` appears anywhere up the parent chain of `id`.
+pub fn is_descendant(categories: &[Category], id: Uuid, ancestor: Uuid) -> bool {
+    let mut current = categories.iter().find(|c| c.id == id).and_then(|c| c.parent);
+    let mut steps = 0;
+    while let Some(parent) = current {
+        if parent == ancestor {
+            return true;
+        }
+        steps += 1;
+        if steps > categories.len() {
+            break;
+        }
+        current = categories.iter().find(|c| c.id == parent).and_then(|c| c.parent);
+    }
+    false
+}
+
+/// Budget amounts per month. Months without their own entry use
+/// `default_amount`, which follows the latest budget set for the current month.
+#[derive(Debug, Clone, Default)]
+pub struct BudgetPlan {
+    /// Currency every amount of the plan is in.
+    pub currency: Currency,
+    pub default_amount: Money,
+    pub periods: BTreeMap<Period, Money>,
+    /// First month whose leftover (or overspend) carries into the next one.
+    /// `None` disables rollover.
+    pub rollover_from: Option<Period>,
+}
+
+impl BudgetPlan {
+    /// The plan with every amount restated in `currency`, keeping the numbers.
+    pub fn in_currency(&self, currency: Currency) -> BudgetPlan {
+        let convert = |amount: Money| Money::from_cents(amount.cents(), currency);
+        BudgetPlan {
+            currency,
+            default_amount: convert(self.default_amount),
+            periods: self.periods.iter().map(|(period, amount)| (*period, convert(*amount))).collect(),
+            rollover_from: self.rollover_from,
+        }
+    }
+
+    pub fn amount_for(&self, period: Period) -> Money {
+        self.periods
+            .get(&period)
+            .copied()
+            .unwrap_or(self.default_amount)
+    }
+
+    /// Unspent budget carried into `period` from the months since
+    /// `rollover_from`. Negative when earlier months were overspent.
+    pub fn carried_into(&self, period: Period, transactions: &[Transaction]) -> Money {
+        let Some(mut current) = self.rollover_from else {
+            return Money::zero(self.currency);
+        };
+        let mut carry = Money::zero(self.currency);
+        while current < period {
+            carry += self.amount_for(current) - period_expenses(transactions, current, self.currency);
+            current = current.next();
+        }
+        carry
+    }
+
+    /// Budget plus anything carried over.
+    pub fn available_for(&self, period: Period, transactions: &[Transaction]) -> Money {
+        self.amount_for(period) + self.carried_into(period, transactions)
+    }
+}
+
+/// The amounts of every line in `period` that are in `currency`. Accounts
+/// kept in another currency are left out rather than added to it.
+fn period_lines(
+    transactions: &[Transaction],
+    period: Period,
+    currency: Currency,
+) -> impl Iterator<Item = (Option<Uuid>, Money)> + '_ {
+    transactions
+        .iter()
+        .filter(move |t| period.contains(t.date))
+        .flat_map(Transaction::lines)
+        .filter(move |(_, amount)| amount.currency() == currency)
+}
+
+/// Income in `currency` during `period`.
+pub fn period_income(transactions: &[Transaction], period: Period, currency: Currency) -> Money {
+    period_lines(transactions, period, currency)
+        .map(|(_, amount)| amount)
+        .filter(|amount| amount.is_positive())
+        .fold(Money::zero(currency), |total, amount| total + amount)
+}
+
+/// Spending in `currency` during `period`, reported as a positive figure.
+pub fn period_expenses(transactions: &[Transaction], period: Period, currency: Currency) -> Money {
+    -period_lines(transactions, period, currency)
+        .map(|(_, amount)| amount)
+        .filter(|amount| amount.is_negative())
+        .fold(Money::zero(currency), |total, amount| total + amount)
+}
+
+/// Per-category totals of income (`income = true`) or spending in `currency`
+/// during `period`, counting every split line separately. Spending is
+/// reported as positive figures; the largest total comes first.
+pub fn category_totals(
+    transactions: &[Transaction],
+    period: Period,
+    currency: Currency,
+    income: bool,
+) -> Vec<(Option<Uuid>, Money)> {
+    let mut totals: Vec<(Option<Uuid>, Money)> = Vec::new();
+    let lines = period_lines(transactions, period, currency).filter(|(_, amount)| if income { amount.is_positive() } else { amount.is_negative() });
+    for (category, amount) in lines {
+        let amount = if income { amount } else { -amount };
+        match totals.iter_mut().find(|(c, _)| *c == category) {
+            Some((_, total)) => *total += amount,
+            None => totals.push((category, amount)),
+        }
+    }
+    totals.sort_by_key(|(_, total)| std::cmp::Reverse(total.cents()));
+    totals
+}
+
+/// Everything the user edits: what gets saved and what undo/redo act on.
+#[derive(Debug, Clone, Default)]
+pub struct BudgetData {
+    pub budget: BudgetPlan,
+    pub accounts: Vec<Account>,
+    pub categories: Vec<Category>,
+    pub transactions: Vec<Transaction>,
+    pub recurring: Vec<RecurringRule>,
+}
+
+impl From<PersistedState> for BudgetData {
+    fn from(state: PersistedState) -> Self {
+        Self {
+            budget: BudgetPlan {
+                currency: state.monthly_budget.currency(),
+                default_amount: state.monthly_budget,
+                periods: state.period_budgets,
+                rollover_from: state.rollover_from,
+            },
+            accounts: state.accounts,
+            categories: state.categories,
+            transactions: state.transactions,
+            recurring: state.recurring,
+        }
+    }
+}
+
+impl From<&BudgetData> for PersistedState {
+    fn from(data: &BudgetData) -> Self {
+        Self {
+            version: schema::CURRENT_VERSION,
+            monthly_budget: data.budget.default_amount,
+            period_budgets: data.budget.periods.clone(),
+            rollover_from: data.budget.rollover_from,
+            accounts: data.accounts.clone(),
+            categories: data.categories.clone(),
+            transactions: data.transactions.clone(),
+            recurring: data.recurring.clone(),
+            unreadable_dates: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedState {
+    /// Schema version the file was written with; see `schema`.
+    #[serde(default)]
+    pub version: u32,
+    /// Default budget for months without their own entry in `period_budgets`.
+    pub monthly_budget: Money,
+    #[serde(default)]
+    pub period_budgets: BTreeMap<Period, Money>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollover_from: Option<Period>,
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    pub transactions: Vec<Transaction>,
+    #[serde(default)]
+    pub recurring: Vec<RecurringRule>,
+    /// Transactions whose pre-1 date could not be read while loading; see
+    /// `date::migrate_legacy_dates`. Reported once and never saved.
+    #[serde(skip)]
+    pub unreadable_dates: Vec<Uuid>,
+}
+
+impl PersistedState {
+    /// Gives every transaction a unique id and makes sure it points at an
+    /// existing account (both ends of a transfer, which must differ), creating
+    /// a default "Cash" account for files saved before accounts existed, and
+    /// drops references to categories that no longer exist. Returns true if
+    /// anything was changed.
+    pub fn normalize(&mut self) -> bool {
+        let mut changed = false;
+        let mut seen = HashSet::new();
+        for tx in &mut self.transactions {
+            if tx.id.is_nil() || !seen.insert(tx.id) {
+                tx.id = Uuid::new_v4();
+                seen.insert(tx.id);
+                changed = true;
+            }
+        }
+        if self.accounts.is_empty() {
+            self.accounts.push(Account::new("Cash", AccountKind::Cash, Money::default()));
+            changed = true;
+        }
+        let fallback = self.accounts[0].id;
+        for tx in &mut self.transactions {
+            if !self.accounts.iter().any(|a| a.id == tx.account) {
+                tx.account = fallback;
+                changed = true;
+            }
+            if let Some(to) = tx.transfer_to
+                && (to == tx.account || !self.accounts.iter().any(|a| a.id == to))
+            {
+                // Another account takes the missing end; with only one
+                // account the money just leaves it.
+                match self.accounts.iter().find(|a| a.id != tx.account) {
+                    Some(other) => tx.transfer_to = Some(other.id),
+                    None => {
+                        tx.transfer_to = None;
+                        tx.amount = -tx.amount;
+                    }
+                }
+                changed = true;
+            }
+            if let Some(category) = tx.category
+                && !self.categories.iter().any(|c| c.id == category)
+            {
+                tx.category = None;
+                changed = true;
+            }
+            for split in &mut tx.splits {
+                if let Some(category) = split.category
+                    && !self.categories.iter().any(|c| c.id == category)
+                {
+                    split.category = None;
+                    changed = true;
+                }
+            }
+        }
+        for rule in &mut self.recurring {
+            if !self.accounts.iter().any(|a| a.id == rule.account) {
+                rule.account = fallback;
+                changed = true;
+            }
+            if let Some(category) = rule.category
+                && !self.categories.iter().any(|c| c.id == category)
+            {
+                rule.category = None;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+pub fn account_balance(account: &Account, transactions: &[Transaction]) -> Money {
+    account.opening_balance
+        + transactions
+            .iter()
+            .map(|t| t.effect_on(account.id))
+            .sum::<Money>()
+}
+
+/// The balance of `account` at the end of `date`.
+pub fn account_balance_on(account: &Account, transactions: &[Transaction], date: NaiveDate) -> Money {
+    account.opening_balance
+        + transactions
+            .iter()
+            .filter(|t| t.date <= date)
+            .map(|t| t.effect_on(account.id))
+            .sum::<Money>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Currency;
+    use chrono::NaiveDate;
+
+    fn transfer(from: Uuid, to: Uuid) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            title: "Savings".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            time: None,
+            amount: Money::from_cents(5000, Currency::PHP),
+            category: None,
+            account: from,
+            recurring: None,
+            splits: Vec::new(),
+            transfer_to: Some(to),
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn never_repairs_a_transfer_into_its_own_account() {
+        let cash = Account::new("Cash", AccountKind::Cash, Money::default());
+        let bank = Account::new("Bank", AccountKind::Bank, Money::default());
+        let mut state = PersistedState {
+            version: crate::schema::CURRENT_VERSION,
+            monthly_budget: Money::default(),
+            period_budgets: BTreeMap::new(),
+            rollover_from: None,
+            accounts: vec![cash.clone(), bank.clone()],
+            categories: Vec::new(),
+            transactions: vec![transfer(cash.id, Uuid::new_v4()), transfer(Uuid::new_v4(), cash.id)],
+            recurring: Vec::new(),
+            unreadable_dates: Vec::new(),
+        };
+        assert!(state.normalize());
+        assert_eq!(state.transactions[0].transfer_to, Some(bank.id));
+        assert_eq!(state.transactions[1].account, cash.id);
+        assert_eq!(state.transactions[1].transfer_to, Some(bank.id));
+
+        // With a single account the transfer becomes money leaving it.
+        state.accounts.truncate(1);
+        state.normalize();
+        let balance = account_balance(&state.accounts[0], &state.transactions);
+        assert!(state.transactions.iter().all(|t| t.transfer_to.is_none() && t.amount.is_negative()));
+        assert_eq!(balance, Money::from_cents(-10_000, Currency::PHP));
+    }
+
+    #[test]
+    fn budget_figures_count_only_their_currency() {
+        let usd: Currency = "USD".parse().unwrap();
+        let php = |cents| Money::from_cents(cents, Currency::PHP);
+        let cash = Account::new("Cash", AccountKind::Cash, php(0));
+        let dollars = Account::new("Dollars", AccountKind::Bank, Money::from_cents(10_000, usd));
+        let entry = |account: &Account, amount: Money| Transaction {
+            transfer_to: None,
+            amount,
+            ..transfer(account.id, account.id)
+        };
+        let transactions = [
+            entry(&cash, php(-30_000)),
+            entry(&cash, php(50_000)),
+            entry(&dollars, Money::from_cents(-2_500, usd)),
+            entry(&dollars, Money::from_cents(4_000, usd)),
+        ];
+        let march = Period::of(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        assert_eq!(period_expenses(&transactions, march, Currency::PHP), php(30_000));
+        assert_eq!(period_income(&transactions, march, Currency::PHP), php(50_000));
+        assert_eq!(period_expenses(&transactions, march, usd), Money::from_cents(2_500, usd));
+        assert_eq!(category_totals(&transactions, march, usd, true), [(None, Money::from_cents(4_000, usd))]);
+
+        let plan = BudgetPlan {
+            default_amount: php(100_000),
+            rollover_from: Some(march),
+            ..BudgetPlan::default()
+        };
+        assert_eq!(plan.available_for(march.next(), &transactions), php(170_000));
+        let in_dollars = plan.in_currency(usd);
+        assert_eq!(in_dollars.available_for(march.next(), &transactions), Money::from_cents(197_500, usd));
+    }
+}
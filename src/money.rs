@@ -0,0 +1,348 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// ISO 4217 currency code, stored as three uppercase ASCII letters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const PHP: Currency = Currency(*b"PHP");
+
+    pub fn as_str(&self) -> &str {
+        // Only ever constructed from ASCII letters.
+        std::str::from_utf8(&self.0).unwrap_or("???")
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::PHP
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.as_bytes() {
+            [a, b, c] if s.bytes().all(|ch| ch.is_ascii_alphabetic()) => Ok(Currency([
+                a.to_ascii_uppercase(),
+                b.to_ascii_uppercase(),
+                c.to_ascii_uppercase(),
+            ])),
+            _ => Err(format!("Invalid currency code \"{}\".", s)),
+        }
+    }
+}
+
+/// An exact amount in minor units (centavos for PHP).
+///
+/// Zero is treated as currency-neutral when adding, so sums can start from
+/// `Money::default()` regardless of the currency being summed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Money {
+    cents: i64,
+    currency: Currency,
+}
+
+impl Money {
+    /// The largest amount `parse` accepts: a trillion, so that sums of many
+    /// such amounts still fit.
+    const MAX_CENTS: i64 = 100_000_000_000_000;
+
+    pub const fn from_cents(cents: i64, currency: Currency) -> Self {
+        Self { cents, currency }
+    }
+
+    pub const fn zero(currency: Currency) -> Self {
+        Self::from_cents(0, currency)
+    }
+
+    /// Rounds a legacy floating point amount to the nearest cent. Only used to
+    /// read files written before amounts were stored exactly.
+    pub fn from_f64_lossy(value: f64, currency: Currency) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        let cents = (value * 100.0).round();
+        if cents.abs() > Self::MAX_CENTS as f64 {
+            return None;
+        }
+        Some(Self::from_cents(cents as i64, currency))
+    }
+
+    /// Parses user input such as "1,234.50", "-12", "+3.5" or "PHP 20".
+    /// Rejects more than two decimal places, a currency code other than
+    /// `currency` and anything that is not a plain decimal number.
+    pub fn parse(input: &str, currency: Currency) -> Result<Self, String> {
+        let text = match split_currency_code(input.trim()) {
+            Some((code, rest)) => {
+                let code: Currency = code.parse()?;
+                if code != currency {
+                    return Err(format!("\"{}\" is in {}, not {}.", input.trim(), code, currency));
+                }
+                rest
+            }
+            None => input.trim(),
+        };
+
+        let invalid = || format!("\"{}\" is not a valid amount.", input.trim());
+        let (negative, digits) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, text),
+        };
+        let digits: String = digits.chars().filter(|c| *c != ',' && *c != '_').collect();
+        let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if !whole.bytes().all(|b| b.is_ascii_digit()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if fraction.len() > 2 {
+            return Err(format!("\"{}\" has more than two decimal places.", input.trim()));
+        }
+
+        let whole: i64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| invalid())?
+        };
+        let fraction: i64 = format!("{:0<2}", fraction).parse().map_err(|_| invalid())?;
+        let cents = whole
+            .checked_mul(100)
+            .and_then(|c| c.checked_add(fraction))
+            .filter(|c| *c <= Self::MAX_CENTS)
+            .ok_or_else(|| format!("\"{}\" is too large.", input.trim()))?;
+        Ok(Self::from_cents(if negative { -cents } else { cents }, currency))
+    }
+
+    pub fn cents(self) -> i64 {
+        self.cents
+    }
+
+    pub fn currency(self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.cents < 0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.cents > 0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.cents == 0
+    }
+
+    pub fn abs(self) -> Self {
+        Self::from_cents(self.cents.abs(), self.currency)
+    }
+
+    fn combined_currency(self, rhs: Money) -> Currency {
+        debug_assert!(
+            self.currency == rhs.currency || self.cents == 0 || rhs.cents == 0,
+            "mixing {} and {} amounts",
+            self.currency,
+            rhs.currency
+        );
+        // A zero sum keeps the currency it started in, so an empty USD
+        // account plus no transactions is still USD.
+        if self.cents == 0 && rhs.cents != 0 { rhs.currency } else { self.currency }
+    }
+}
+
+/// Splits a leading or trailing three-letter code ("PHP 20", "20 PHP") off an amount.
+fn split_currency_code(text: &str) -> Option<(&str, &str)> {
+    let is_code = |code: &&str| code.bytes().all(|b| b.is_ascii_alphabetic());
+    if let Some(code) = text.get(..3).filter(is_code) {
+        return Some((code, text[3..].trim()));
+    }
+    let split = text.len().checked_sub(3)?;
+    let code = text.get(split..).filter(is_code)?;
+    Some((code, text[..split].trim()))
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, rhs: Money) -> Money {
+        let cents = self.cents.checked_add(rhs.cents).expect("amount overflow");
+        Money::from_cents(cents, self.combined_currency(rhs))
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, rhs: Money) -> Money {
+        let cents = self.cents.checked_sub(rhs.cents).expect("amount overflow");
+        Money::from_cents(cents, self.combined_currency(rhs))
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money::from_cents(-self.cents, self.currency)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::default(), |acc, m| acc + m)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+/// Reads a stored amount, taking the currency from its code ("-458.00 PHP")
+/// and falling back to PHP when there is none.
+impl FromStr for Money {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let currency = match split_currency_code(s.trim()) {
+            Some((code, _)) => code.parse()?,
+            None => Currency::default(),
+        };
+        Money::parse(s, currency)
+    }
+}
+
+/// Formats the number only ("-1234.56"); the `+` flag forces a sign.
+/// The alternate flag (`{:#}`) adds thousands separators.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.cents < 0 {
+            "-"
+        } else if f.sign_plus() {
+            "+"
+        } else {
+            ""
+        };
+        let abs = self.cents.unsigned_abs();
+        let mut whole = (abs / 100).to_string();
+        if f.alternate() {
+            let mut grouped = String::new();
+            for (i, ch) in whole.chars().enumerate() {
+                if i > 0 && (whole.len() - i).is_multiple_of(3) {
+                    grouped.push(',');
+                }
+                grouped.push(ch);
+            }
+            whole = grouped;
+        }
+        write!(f, "{}{}.{:02}", sign, whole, abs % 100)
+    }
+}
+
+/// Stored as "-458.00 PHP" so the JSON stays readable and exact.
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{} {}", self, self.currency))
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            // Files written before amounts were exact store plain numbers.
+            Legacy(f64),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => text.parse().map_err(de::Error::custom),
+            Raw::Legacy(value) => Money::from_f64_lossy(value, Currency::default())
+                .ok_or_else(|| de::Error::custom(format!("invalid amount {}", value))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn php(cents: i64) -> Money {
+        Money::from_cents(cents, Currency::PHP)
+    }
+
+    #[test]
+    fn parses_separators_signs_and_codes() {
+        assert_eq!(Money::parse("1,234.50", Currency::PHP), Ok(php(123_450)));
+        assert_eq!(Money::parse("1_000", Currency::PHP), Ok(php(100_000)));
+        assert_eq!(Money::parse(" -12 ", Currency::PHP), Ok(php(-1200)));
+        assert_eq!(Money::parse("+3.5", Currency::PHP), Ok(php(350)));
+        assert_eq!(Money::parse(".25", Currency::PHP), Ok(php(25)));
+        assert_eq!(Money::parse("PHP 20", Currency::PHP), Ok(php(2000)));
+        assert_eq!(Money::parse("20 php", Currency::PHP), Ok(php(2000)));
+
+        let usd: Currency = "USD".parse().unwrap();
+        assert_eq!(Money::parse("100 USD", usd), Ok(Money::from_cents(10_000, usd)));
+        assert!(Money::parse("100 USD", Currency::PHP).unwrap_err().contains("USD, not PHP"));
+        assert_eq!("-458.00 USD".parse(), Ok(Money::from_cents(-45_800, usd)));
+        assert_eq!("7".parse(), Ok(php(700)));
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        assert!(Money::parse("1.005", Currency::PHP).unwrap_err().contains("two decimal places"));
+        for input in ["", "-", ".", "inf", "NaN", "1e3", "1.2.3", "12abc", "--5"] {
+            assert!(Money::parse(input, Currency::PHP).is_err(), "{:?} parsed", input);
+        }
+        assert!(Money::parse("1000000000000.01", Currency::PHP).unwrap_err().contains("too large"));
+        assert_eq!(Money::parse("1000000000000", Currency::PHP), Ok(php(Money::MAX_CENTS)));
+    }
+
+    #[test]
+    fn displays_with_sign_and_grouping() {
+        assert_eq!(php(-123_456_789).to_string(), "-1234567.89");
+        assert_eq!(format!("{:#}", php(123_456_789)), "1,234,567.89");
+        assert_eq!(format!("{:+#}", php(100_000)), "+1,000.00");
+        assert_eq!(format!("{:+}", php(0)), "+0.00");
+        assert_eq!(php(-5).to_string(), "-0.05");
+        assert_eq!(serde_json::to_string(&php(-45_800)).unwrap(), "\"-458.00 PHP\"");
+    }
+
+    #[test]
+    fn zero_takes_the_other_currency() {
+        let usd: Currency = "USD".parse().unwrap();
+        let total = Money::default() + Money::from_cents(150, usd);
+        assert_eq!(total, Money::from_cents(150, usd));
+        assert_eq!([php(100), php(-250)].iter().sum::<Money>(), php(-150));
+        assert_eq!(Money::zero(usd) + Money::default(), Money::zero(usd));
+    }
+}
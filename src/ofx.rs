@@ -0,0 +1,285 @@
+//! Reading OFX and QFX bank statements.
+//!
+//! OFX 1.x is SGML where simple elements have no closing tag
+//! (`<TRNAMT>-45.00`); 2.x is XML. Both are read by the same tolerant
+//! scanner: an element followed by text is a value, anything else opens an
+//! aggregate that a later closing tag ends.
+
+use crate::model::{StatementBalance, Transaction};
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// One `<STMTTRN>` of a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementLine {
+    /// The bank's id for the transaction, unique within the account.
+    pub fitid: String,
+    pub date: NaiveDate,
+    pub amount: Money,
+    pub name: String,
+    pub memo: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Statement {
+    /// `<ACCTID>` of the statement's account, if given.
+    pub account_id: Option<String>,
+    pub lines: Vec<StatementLine>,
+    /// `<LEDGERBAL>`: the bank's balance at the end of the statement.
+    pub ledger_balance: Option<StatementBalance>,
+    /// Why each unreadable `<STMTTRN>` was left out.
+    pub skipped: Vec<String>,
+}
+
+impl Statement {
+    /// The lines whose FITID is not yet on a transaction of `account`.
+    pub fn new_lines(&self, account: Uuid, transactions: &[Transaction]) -> Vec<&StatementLine> {
+        let mut seen: HashSet<&str> = transactions
+            .iter()
+            .filter(|t| t.account == account)
+            .filter_map(|t| t.import_id.as_deref())
+            .collect();
+        self.lines.iter().filter(|line| seen.insert(&line.fitid)).collect()
+    }
+
+    /// The currency of the amounts, or `None` for a statement without any.
+    pub fn currency(&self) -> Option<Currency> {
+        let balance = self.ledger_balance.map(|b| b.amount);
+        self.lines.iter().map(|l| l.amount).chain(balance).next().map(Money::currency)
+    }
+}
+
+impl StatementLine {
+    /// The line as a transaction in `account`, remembering the FITID so the
+    /// same line is recognised when the next statement overlaps.
+    pub fn to_transaction(&self, account: Uuid) -> Transaction {
+        let title = match (self.name.is_empty(), self.memo.is_empty()) {
+            (false, _) => self.name.clone(),
+            (true, false) => self.memo.clone(),
+            (true, true) => "Imported".to_string(),
+        };
+        Transaction {
+            id: Uuid::new_v4(),
+            title,
+            date: self.date,
+            time: None,
+            amount: self.amount,
+            category: None,
+            account,
+            recurring: None,
+            splits: Vec::new(),
+            transfer_to: None,
+            import_id: Some(self.fitid.clone()),
+        }
+    }
+}
+
+pub fn read(path: &Path) -> Result<Statement, String> {
+    let bytes = fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    parse(&String::from_utf8_lossy(&bytes))
+}
+
+/// A value or an aggregate found by `scan`.
+#[derive(Debug)]
+enum Node {
+    Value(String, String),
+    Aggregate(String, Vec<Node>),
+}
+
+pub fn parse(text: &str) -> Result<Statement, String> {
+    let start = text
+        .find("<OFX>")
+        .or_else(|| text.find("<ofx>"))
+        .ok_or_else(|| "This is not an OFX file: no <OFX> element.".to_string())?;
+    let nodes = scan(&text[start..]);
+    let mut statement = Statement::default();
+    let mut currency = Currency::default();
+    // A statement is visited before its transactions, so its currency is known by then.
+    walk(&nodes, &mut |name, children| match name {
+        "STMTRS" | "CCSTMTRS" => {
+            if let Some(code) = value(children, "CURDEF").and_then(|c| c.parse().ok()) {
+                currency = code;
+            }
+        }
+        "BANKACCTFROM" | "CCACCTFROM" => {
+            statement.account_id = value(children, "ACCTID").map(str::to_string);
+        }
+        "STMTTRN" => match statement_line(children, currency) {
+            Ok(line) => statement.lines.push(line),
+            Err(err) => {
+                let fitid = value(children, "FITID").unwrap_or("without FITID");
+                statement.skipped.push(format!("Transaction {}: {}", fitid, err));
+            }
+        },
+        "LEDGERBAL" => {
+            let amount = value(children, "BALAMT").and_then(|a| amount(a, currency).ok());
+            let date = value(children, "DTASOF").and_then(date);
+            if let (Some(amount), Some(date)) = (amount, date) {
+                statement.ledger_balance = Some(StatementBalance { amount, date });
+            }
+        }
+        _ => {}
+    });
+    Ok(statement)
+}
+
+fn statement_line(children: &[Node], currency: Currency) -> Result<StatementLine, String> {
+    let field = |name| value(children, name).ok_or_else(|| format!("no <{}>.", name));
+    let posted = field("DTPOSTED")?;
+    Ok(StatementLine {
+        fitid: field("FITID")?.to_string(),
+        date: date(posted).ok_or_else(|| format!("\"{}\" is not a date.", posted))?,
+        amount: amount(field("TRNAMT")?, currency)?,
+        name: value(children, "NAME").or_else(|| value(children, "PAYEE")).unwrap_or_default().to_string(),
+        memo: value(children, "MEMO").unwrap_or_default().to_string(),
+    })
+}
+
+/// Calls `visit` with every aggregate's name and children, depth first.
+fn walk(nodes: &[Node], visit: &mut impl FnMut(&str, &[Node])) {
+    for node in nodes {
+        if let Node::Aggregate(name, children) = node {
+            visit(name, children);
+            walk(children, visit);
+        }
+    }
+}
+
+fn value<'a>(children: &'a [Node], name: &str) -> Option<&'a str> {
+    children.iter().find_map(|node| match node {
+        Node::Value(tag, value) if tag == name => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+/// OFX dates start with `YYYYMMDD`; time and zone are ignored.
+fn date(text: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text.get(..8)?, "%Y%m%d").ok()
+}
+
+/// Some banks write a decimal comma.
+fn amount(text: &str, currency: Currency) -> Result<Money, String> {
+    let text = if text.contains('.') { text.to_string() } else { text.replace(',', ".") };
+    Money::parse(&text, currency)
+}
+
+/// Builds the element tree, closing SGML values implicitly.
+fn scan(text: &str) -> Vec<Node> {
+    // Open aggregates, each with the children gathered so far.
+    let mut stack: Vec<(String, Vec<Node>)> = vec![(String::new(), Vec::new())];
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        let tag = rest[open + 1..open + close].trim();
+        rest = &rest[open + close + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_uppercase();
+            // A closing tag for a value that was already taken is ignored;
+            // one for an open aggregate closes everything inside it.
+            if let Some(depth) = stack.iter().rposition(|(open, _)| *open == name)
+                && depth > 0
+            {
+                while stack.len() > depth {
+                    let (name, children) = stack.pop().unwrap_or_default();
+                    if let Some((_, parent)) = stack.last_mut() {
+                        parent.push(Node::Aggregate(name, children));
+                    }
+                }
+            }
+            continue;
+        }
+        let name = tag.trim_end_matches('/').trim().to_ascii_uppercase();
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        let content = rest[..text_end].trim();
+        if content.is_empty() {
+            stack.push((name, Vec::new()));
+        } else {
+            if let Some((_, children)) = stack.last_mut() {
+                children.push(Node::Value(name, decode(content)));
+            }
+            rest = &rest[text_end..];
+        }
+    }
+    while stack.len() > 1 {
+        let (name, children) = stack.pop().unwrap_or_default();
+        if let Some((_, parent)) = stack.last_mut() {
+            parent.push(Node::Aggregate(name, children));
+        }
+    }
+    stack.pop().map(|(_, nodes)| nodes).unwrap_or_default()
+}
+
+fn decode(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGML: &str = "OFXHEADER:100\nDATA:OFXSGML\nVERSION:102\n\n<OFX>\n<BANKMSGSRSV1><STMTTRNRS><STMTRS>\n\
+        <CURDEF>PHP\n<BANKACCTFROM><BANKID>123<ACCTID>9876<ACCTTYPE>CHECKING</BANKACCTFROM>\n\
+        <BANKTRANLIST><DTSTART>20240101<DTEND>20240131\n\
+        <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240105120000.000[+8:PHT]<TRNAMT>-45.50<FITID>A1\
+        <NAME>Jollibee &amp; Co<MEMO>Card 1234</STMTTRN>\n\
+        <STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240115<TRNAMT>25000.00<FITID>A2<MEMO>Payroll</STMTTRN>\n\
+        <STMTTRN><TRNTYPE>FEE<DTPOSTED>20240120<TRNAMT>-0.125<FITID>A3</STMTTRN>\n\
+        <STMTTRN><TRNTYPE>FEE<DTPOSTED>20240121<TRNAMT>-1.00</STMTTRN>\n\
+        </BANKTRANLIST><LEDGERBAL><BALAMT>24954.50<DTASOF>20240131</LEDGERBAL>\n\
+        </STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>";
+
+    const XML: &str = "<?xml version=\"1.0\"?><?OFX OFXHEADER=\"200\" VERSION=\"220\"?>\
+        <OFX><CREDITCARDMSGSRSV1><CCSTMTTRNRS><CCSTMTRS><CURDEF>USD</CURDEF>\
+        <CCACCTFROM><ACCTID>4111</ACCTID></CCACCTFROM><BANKTRANLIST>\
+        <STMTTRN><TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20240203</DTPOSTED><TRNAMT>-12.00</TRNAMT>\
+        <FITID>X9</FITID><NAME>Books</NAME></STMTTRN></BANKTRANLIST>\
+        <LEDGERBAL><BALAMT>-12.00</BALAMT><DTASOF>20240229</DTASOF></LEDGERBAL></CCSTMTRS>\
+        </CCSTMTTRNRS></CREDITCARDMSGSRSV1></OFX>";
+
+    #[test]
+    fn reads_sgml_statements() {
+        let statement = parse(SGML).unwrap();
+        let php = |cents| Money::from_cents(cents, Currency::PHP);
+        assert_eq!(statement.account_id.as_deref(), Some("9876"));
+        assert_eq!(statement.lines.len(), 2);
+        assert_eq!(statement.skipped.len(), 2);
+        assert!(statement.skipped[0].starts_with("Transaction A3:"));
+        let first = &statement.lines[0];
+        assert_eq!(first.fitid, "A1");
+        assert_eq!(first.date, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap());
+        assert_eq!(first.amount, php(-4550));
+        assert_eq!(first.name, "Jollibee & Co");
+        assert_eq!(statement.lines[1].to_transaction(Uuid::nil()).title, "Payroll");
+        let balance = statement.ledger_balance.unwrap();
+        assert_eq!(balance.amount, php(2_495_450));
+        assert_eq!(balance.date, NaiveDate::from_ymd_opt(2024, 1, 31).unwrap());
+    }
+
+    #[test]
+    fn reads_xml_statements() {
+        let statement = parse(XML).unwrap();
+        let usd: Currency = "USD".parse().unwrap();
+        assert_eq!(statement.account_id.as_deref(), Some("4111"));
+        assert_eq!(statement.lines.len(), 1);
+        assert_eq!(statement.lines[0].amount, Money::from_cents(-1200, usd));
+        let mut tx = statement.lines[0].to_transaction(Uuid::nil());
+        assert!(statement.new_lines(Uuid::nil(), std::slice::from_ref(&tx)).is_empty());
+        tx.account = Uuid::new_v4();
+        assert_eq!(statement.new_lines(Uuid::nil(), &[tx]).len(), 1);
+        assert_eq!(statement.ledger_balance.map(|b| b.amount), Some(Money::from_cents(-1200, usd)));
+    }
+}
@@ -0,0 +1,61 @@
+//! Where DYBudget keeps its files.
+
+use directories::ProjectDirs;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DATA_FILE_NAME: &str = "budget_data.json";
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "DYBudget")
+}
+
+/// Per-user data folder: `$XDG_DATA_HOME/dybudget` on Linux,
+/// `%APPDATA%\DYBudget\data` on Windows and `~/Library/Application Support/DYBudget`
+/// on macOS. Falls back to the working directory if no home folder is known.
+pub fn data_dir() -> PathBuf {
+    project_dirs().map_or_else(|| PathBuf::from("."), |dirs| dirs.data_dir().to_path_buf())
+}
+
+/// Per-user folder for `settings.json`.
+pub fn config_dir() -> PathBuf {
+    project_dirs().map_or_else(|| PathBuf::from("."), |dirs| dirs.config_dir().to_path_buf())
+}
+
+pub fn default_data_file() -> PathBuf {
+    data_dir().join(DATA_FILE_NAME)
+}
+
+/// Value of `--data-file <path>` or `--data-file=<path>`, if given.
+pub fn data_file_arg(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--data-file" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--data-file=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Moves a `budget_data.json` left in the working directory by older
+/// versions to `target`, unless `target` already exists. Returns the path
+/// that was moved.
+pub fn migrate_working_dir_file(target: &Path) -> Result<Option<PathBuf>, String> {
+    let legacy = PathBuf::from(DATA_FILE_NAME);
+    if target.exists() || !legacy.is_file() {
+        return Ok(None);
+    }
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+    }
+    // Renaming fails across drives, so fall back to copying.
+    if fs::rename(&legacy, target).is_err() {
+        fs::copy(&legacy, target)
+            .map_err(|err| format!("Could not copy {} to {}: {}", legacy.display(), target.display(), err))?;
+        let _ = fs::remove_file(&legacy);
+    }
+    Ok(Some(legacy))
+}
@@ -0,0 +1,550 @@
+//! Reading and writing QIF, the Quicken Interchange Format older finance
+//! programs use to move data in and out.
+//!
+//! Only the money sections are handled: `!Account` headers, the category
+//! list and `Bank`, `Cash`, `CCard` and other-asset/liability registers.
+//! Investment registers and memorized transactions are skipped.
+
+use crate::model::{Account, AccountKind, Category, PersistedState, Split, Transaction, category_path};
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use egui::Color32;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Payee QIF uses for the record that sets an account's starting balance.
+const OPENING_BALANCE: &str = "Opening Balance";
+
+/// Where a transaction or split line goes: a category such as
+/// `Food:Takeout`, or another account, written `[Savings]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Category(String),
+    Transfer(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitLine {
+    pub target: Option<Target>,
+    pub amount: Money,
+    pub memo: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub date: NaiveDate,
+    pub amount: Money,
+    pub payee: String,
+    pub memo: String,
+    pub target: Option<Target>,
+    pub splits: Vec<SplitLine>,
+}
+
+/// One register. `account` is `None` when the file has no `!Account`
+/// header, as in a single-account export.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub account: Option<String>,
+    pub kind: AccountKind,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QifFile {
+    /// Names from the `!Type:Cat` list.
+    pub categories: Vec<String>,
+    pub sections: Vec<Section>,
+    /// A message for each record that could not be read.
+    pub skipped: Vec<String>,
+}
+
+pub fn read(path: &Path, day_first: bool) -> Result<QifFile, String> {
+    let bytes = fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    parse(&String::from_utf8_lossy(&bytes), day_first)
+}
+
+/// What the lines after a `!` header describe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Account,
+    Categories,
+    Register,
+    Skip,
+}
+
+/// Parses the text of a QIF file. `day_first` reads "05/01/2024" as
+/// 5 January instead of May 1, as some non-US programs write it.
+pub fn parse(text: &str, day_first: bool) -> Result<QifFile, String> {
+    let mut file = QifFile::default();
+    let mut mode = None;
+    // The last `!Account` record, which the next register belongs to.
+    let mut account: Option<(String, AccountKind)> = None;
+    let mut record: Vec<(char, String)> = Vec::new();
+    let mut record_line = 0;
+    for (index, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('!') {
+            let header = header.trim();
+            let kind = header.split_once(':').map(|(_, kind)| kind.trim().to_ascii_lowercase());
+            mode = match header.to_ascii_lowercase().as_str() {
+                "account" => Some(Mode::Account),
+                "option:autoswitch" | "clear:autoswitch" => mode,
+                _ => match kind.as_deref() {
+                    Some("cat") => Some(Mode::Categories),
+                    Some(kind) if register_kind(kind).is_some() => {
+                        let (name, kind) = match account.take() {
+                            Some((name, kind)) => (Some(name), kind),
+                            None => (None, register_kind(kind).unwrap_or(AccountKind::Bank)),
+                        };
+                        file.sections.push(Section {
+                            account: name,
+                            kind,
+                            entries: Vec::new(),
+                        });
+                        Some(Mode::Register)
+                    }
+                    _ => Some(Mode::Skip),
+                },
+            };
+            record.clear();
+            continue;
+        }
+        if record.is_empty() {
+            record_line = index + 1;
+        }
+        if line.starts_with('^') {
+            let fields = std::mem::take(&mut record);
+            match mode {
+                Some(Mode::Account) => {
+                    let name = field(&fields, 'N').unwrap_or_default().to_string();
+                    let kind = field(&fields, 'T').and_then(|t| register_kind(&t.to_ascii_lowercase()));
+                    account = Some((name, kind.unwrap_or(AccountKind::Bank)));
+                }
+                Some(Mode::Categories) => {
+                    if let Some(name) = field(&fields, 'N') {
+                        file.categories.push(name.to_string());
+                    }
+                }
+                Some(Mode::Register) => match entry(&fields, day_first) {
+                    Ok(entry) => {
+                        if let Some(section) = file.sections.last_mut() {
+                            section.entries.push(entry);
+                        }
+                    }
+                    Err(err) => file.skipped.push(format!("Line {}: {}", record_line, err)),
+                },
+                Some(Mode::Skip) => {}
+                None => return Err("This is not a QIF file: it does not start with a !Type header.".to_string()),
+            }
+            continue;
+        }
+        let mut chars = line.chars();
+        if let Some(code) = chars.next() {
+            record.push((code, chars.as_str().trim().to_string()));
+        }
+    }
+    if mode.is_none() {
+        return Err("This is not a QIF file: it does not start with a !Type header.".to_string());
+    }
+    Ok(file)
+}
+
+/// The account kind of a register type such as `Bank` or `CCard`, after
+/// `!Type:`, or `None` for registers that are not read.
+fn register_kind(kind: &str) -> Option<AccountKind> {
+    match kind {
+        "bank" => Some(AccountKind::Bank),
+        "cash" => Some(AccountKind::Cash),
+        "ccard" | "oth l" => Some(AccountKind::CreditCard),
+        "oth a" => Some(AccountKind::Bank),
+        _ => None,
+    }
+}
+
+fn field(fields: &[(char, String)], code: char) -> Option<&str> {
+    fields.iter().find(|(c, _)| *c == code).map(|(_, value)| value.as_str())
+}
+
+fn entry(fields: &[(char, String)], day_first: bool) -> Result<Entry, String> {
+    let date_text = field(fields, 'D').ok_or("The record has no date.")?;
+    let date = date(date_text, day_first).ok_or_else(|| format!("\"{}\" is not a date.", date_text))?;
+    let amount_text = field(fields, 'T').or_else(|| field(fields, 'U')).ok_or("The record has no amount.")?;
+    let amount = Money::parse(amount_text, Currency::default())?;
+    let mut splits: Vec<SplitLine> = Vec::new();
+    for (code, value) in fields {
+        match code {
+            'S' => splits.push(SplitLine {
+                target: target(value),
+                amount: Money::default(),
+                memo: String::new(),
+            }),
+            'E' => {
+                if let Some(split) = splits.last_mut() {
+                    split.memo = value.clone();
+                }
+            }
+            '$' => {
+                if let Some(split) = splits.last_mut() {
+                    split.amount = Money::parse(value, Currency::default())?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Entry {
+        date,
+        amount,
+        payee: field(fields, 'P').unwrap_or_default().to_string(),
+        memo: field(fields, 'M').unwrap_or_default().to_string(),
+        target: field(fields, 'L').and_then(target),
+        splits,
+    })
+}
+
+/// Reads an `L` or `S` field; a class after `/` is dropped.
+fn target(text: &str) -> Option<Target> {
+    let text = text.split('/').next().unwrap_or_default().trim();
+    if let Some(account) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        Some(Target::Transfer(account.trim().to_string()))
+    } else if text.is_empty() {
+        None
+    } else {
+        Some(Target::Category(text.to_string()))
+    }
+}
+
+/// Quicken writes "1/ 5/24", "1/5'24" (an apostrophe marks 2000 onwards),
+/// "01/05/2024" and sometimes "2024-01-05".
+fn date(text: &str, day_first: bool) -> Option<NaiveDate> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Ok(date) = NaiveDate::parse_from_str(&text, "%Y-%m-%d") {
+        return Some(date);
+    }
+    let apostrophe = text.contains('\'');
+    let parts: Vec<&str> = text.split(['/', '\'', '.', '-']).collect();
+    let [first, second, year] = parts.as_slice() else {
+        return None;
+    };
+    let (day, month) = if day_first { (first, second) } else { (second, first) };
+    let mut year: i32 = year.parse().ok()?;
+    if year < 100 {
+        year += if apostrophe || year < 70 { 2000 } else { 1900 };
+    }
+    NaiveDate::from_ymd_opt(year, month.parse().ok()?, day.parse().ok()?)
+}
+
+/// What importing a QIF file adds to a budget.
+#[derive(Debug, Clone, Default)]
+pub struct Import {
+    pub accounts: Vec<Account>,
+    pub categories: Vec<Category>,
+    pub transactions: Vec<Transaction>,
+}
+
+/// Maps a parsed file onto the budget's accounts and categories, matching
+/// them by name and creating the ones that do not exist yet. Registers
+/// without an `!Account` header go into `default_account`.
+///
+/// A transfer appears in the registers of both accounts; the second copy is
+/// recognised and left out. New accounts take their opening balance from
+/// the "Opening Balance" record and the currency of `default_account`.
+pub fn import(
+    file: &QifFile,
+    accounts: &[Account],
+    categories: &[Category],
+    default_account: Uuid,
+    color: Color32,
+) -> Import {
+    let currency = accounts
+        .iter()
+        .find(|a| a.id == default_account)
+        .map_or_else(Currency::default, |a| a.opening_balance.currency());
+    let mut importer = Importer {
+        accounts: accounts.to_vec(),
+        categories: categories.to_vec(),
+        existing_accounts: accounts.len(),
+        existing_categories: categories.len(),
+        currency,
+        color,
+        transfers: Vec::new(),
+        transactions: Vec::new(),
+    };
+    for name in &file.categories {
+        importer.category(name);
+    }
+    for section in &file.sections {
+        let account = match &section.account {
+            Some(name) => importer.account(name, section.kind),
+            None => default_account,
+        };
+        for entry in &section.entries {
+            importer.entry(account, entry);
+        }
+    }
+    Import {
+        accounts: importer.accounts.split_off(importer.existing_accounts),
+        categories: importer.categories.split_off(importer.existing_categories),
+        transactions: importer.transactions,
+    }
+}
+
+struct Importer {
+    /// The budget's accounts followed by the ones created so far.
+    accounts: Vec<Account>,
+    categories: Vec<Category>,
+    existing_accounts: usize,
+    existing_categories: usize,
+    currency: Currency,
+    color: Color32,
+    /// Transfers added so far as (from, to, date, cents), so the copy in the
+    /// other account's register is skipped once.
+    transfers: Vec<(Uuid, Uuid, NaiveDate, i64)>,
+    transactions: Vec<Transaction>,
+}
+
+impl Importer {
+    fn account(&mut self, name: &str, kind: AccountKind) -> Uuid {
+        if let Some(account) = self.accounts.iter().find(|a| a.name.eq_ignore_ascii_case(name)) {
+            return account.id;
+        }
+        let account = Account::new(name, kind, Money::zero(self.currency));
+        let id = account.id;
+        self.accounts.push(account);
+        id
+    }
+
+    /// The category at a `Parent:Child` path, created level by level.
+    fn category(&mut self, path: &str) -> Option<Uuid> {
+        let mut parent = None;
+        for name in path.split(':').map(str::trim).filter(|n| !n.is_empty()) {
+            let found = self
+                .categories
+                .iter()
+                .find(|c| c.parent == parent && c.name.eq_ignore_ascii_case(name))
+                .map(|c| c.id);
+            parent = Some(found.unwrap_or_else(|| {
+                let category = Category::new(name, self.color, "", parent);
+                let id = category.id;
+                self.categories.push(category);
+                id
+            }));
+        }
+        parent
+    }
+
+    fn money(&self, amount: Money) -> Money {
+        Money::from_cents(amount.cents(), self.currency)
+    }
+
+    fn entry(&mut self, account: Uuid, entry: &Entry) {
+        let amount = self.money(entry.amount);
+        if let Some(Target::Transfer(other)) = &entry.target {
+            let own = self.accounts.iter().find(|a| a.id == account).map(|a| a.name.clone());
+            if entry.payee.eq_ignore_ascii_case(OPENING_BALANCE)
+                && own.is_some_and(|own| own.eq_ignore_ascii_case(other))
+            {
+                // Only accounts this import creates take the balance; the
+                // others already have one.
+                if let Some(new) = self.accounts[self.existing_accounts..].iter_mut().find(|a| a.id == account) {
+                    new.opening_balance = amount;
+                }
+                return;
+            }
+            if entry.splits.is_empty() {
+                let other = self.account(other, AccountKind::Bank);
+                let (from, to) = if amount.is_negative() { (account, other) } else { (other, account) };
+                let key = (from, to, entry.date, amount.cents().abs());
+                if let Some(index) = self.transfers.iter().position(|t| *t == key) {
+                    self.transfers.remove(index);
+                    return;
+                }
+                self.transfers.push(key);
+                self.push(entry, from, amount.abs(), None, Vec::new(), Some(to));
+                return;
+            }
+        }
+        let mut splits: Vec<Split> = entry
+            .splits
+            .iter()
+            .map(|line| {
+                let (category, memo) = match &line.target {
+                    Some(Target::Category(path)) => (self.category(path), line.memo.clone()),
+                    // Split lines cannot move money between accounts here,
+                    // so the account is kept in the memo.
+                    Some(Target::Transfer(other)) if line.memo.is_empty() => (None, format!("[{}]", other)),
+                    Some(Target::Transfer(other)) => (None, format!("{} [{}]", line.memo, other)),
+                    None => (None, line.memo.clone()),
+                };
+                Split {
+                    category,
+                    amount: self.money(line.amount),
+                    memo,
+                }
+            })
+            .collect();
+        if !splits.is_empty() {
+            let rest = amount - splits.iter().map(|s| s.amount).sum::<Money>();
+            if !rest.is_zero() {
+                splits.push(Split {
+                    category: None,
+                    amount: rest,
+                    memo: String::new(),
+                });
+            }
+        }
+        let category = match &entry.target {
+            Some(Target::Category(path)) if splits.is_empty() => self.category(path),
+            _ => None,
+        };
+        self.push(entry, account, amount, category, splits, None);
+    }
+
+    fn push(
+        &mut self,
+        entry: &Entry,
+        account: Uuid,
+        amount: Money,
+        category: Option<Uuid>,
+        splits: Vec<Split>,
+        transfer_to: Option<Uuid>,
+    ) {
+        let title = [&entry.payee, &entry.memo]
+            .into_iter()
+            .find(|text| !text.is_empty())
+            .map_or_else(|| "Imported".to_string(), |text| text.clone());
+        self.transactions.push(Transaction {
+            id: Uuid::new_v4(),
+            title,
+            date: entry.date,
+            time: None,
+            amount,
+            category,
+            account,
+            recurring: None,
+            splits,
+            transfer_to,
+            import_id: None,
+        });
+    }
+}
+
+/// Writes every account as its own register, with the category list first.
+/// Transfers appear in both accounts' registers, as QIF expects.
+pub fn write(state: &PersistedState) -> String {
+    let mut out = String::new();
+    let path = |id: Option<Uuid>| {
+        id.map(|id| category_path(&state.categories, id).replace(':', "-").replace(" / ", ":"))
+            .unwrap_or_default()
+    };
+    if !state.categories.is_empty() {
+        out.push_str("!Type:Cat\n");
+        for category in &state.categories {
+            out.push_str(&format!("N{}\n^\n", path(Some(category.id))));
+        }
+    }
+    let name = |id: Uuid| state.accounts.iter().find(|a| a.id == id).map(|a| a.name.as_str()).unwrap_or_default();
+    for account in &state.accounts {
+        let kind = match account.kind {
+            AccountKind::Bank => "Bank",
+            AccountKind::Cash | AccountKind::EWallet => "Cash",
+            AccountKind::CreditCard => "CCard",
+        };
+        out.push_str(&format!("!Account\nN{}\nT{}\n^\n!Type:{}\n", account.name, kind, kind));
+        let mut transactions: Vec<&Transaction> = state
+            .transactions
+            .iter()
+            .filter(|t| t.account == account.id || t.transfer_to == Some(account.id))
+            .collect();
+        transactions.sort_by_key(|t| (t.date, t.time));
+        let start = transactions.first().map_or_else(crate::date::today, |t| t.date);
+        out.push_str(&format!(
+            "D{}\nT{}\nCX\nP{}\nL[{}]\n^\n",
+            start.format("%m/%d/%Y"),
+            account.opening_balance,
+            OPENING_BALANCE,
+            account.name
+        ));
+        for tx in transactions {
+            out.push_str(&format!("D{}\nT{}\nP{}\n", tx.date.format("%m/%d/%Y"), tx.effect_on(account.id), tx.title));
+            if let Some(to) = tx.transfer_to {
+                let other = if tx.account == account.id { to } else { tx.account };
+                out.push_str(&format!("L[{}]\n", name(other)));
+            } else if tx.splits.is_empty() {
+                if tx.category.is_some() {
+                    out.push_str(&format!("L{}\n", path(tx.category)));
+                }
+            } else {
+                for split in &tx.splits {
+                    out.push_str(&format!("S{}\n", path(split.category)));
+                    if !split.memo.is_empty() {
+                        out.push_str(&format!("E{}\n", split.memo));
+                    }
+                    out.push_str(&format!("${}\n", split.amount));
+                }
+            }
+            out.push_str("^\n");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema;
+
+    const QUICKEN: &str = "!Type:Cat\nNFood:Groceries\nE\n^\n\
+        !Account\nNChecking\nTBank\n^\n!Type:Bank\n\
+        D1/ 1'24\nT1,000.00\nCX\nPOpening Balance\nL[Checking]\n^\n\
+        D1/ 5'24\nT-85.20\nPMarket\nLFood:Groceries\n^\n\
+        D1/ 6'24\nT-150.00\nPPharmacy and food\nSHealth\nEVitamins\n$-100.00\nSFood:Groceries\n$-40.00\n^\n\
+        D1/ 7'24\nT-200.00\nPTo savings\nL[Savings]\n^\n\
+        D13/13/24\nT-1.00\n^\n\
+        !Account\nNSavings\nTBank\n^\n!Type:Bank\n\
+        D1/ 7'24\nT200.00\nPFrom checking\nL[Checking]\n^\n";
+
+    #[test]
+    fn imports_registers_splits_and_transfers() {
+        let file = parse(QUICKEN, false).unwrap();
+        assert_eq!(file.sections.len(), 2);
+        assert_eq!(file.skipped.len(), 1);
+        let import = import(&file, &[], &[], Uuid::nil(), Color32::WHITE);
+        let names: Vec<&str> = import.accounts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["Checking", "Savings"]);
+        assert_eq!(import.accounts[0].opening_balance.cents(), 100_000);
+        let paths: Vec<String> = import.categories.iter().map(|c| category_path(&import.categories, c.id)).collect();
+        assert_eq!(paths, ["Food", "Food / Groceries", "Health"]);
+
+        // The transfer shows up in both registers but is imported once.
+        assert_eq!(import.transactions.len(), 3);
+        let split = &import.transactions[1];
+        assert_eq!(split.date, NaiveDate::from_ymd_opt(2024, 1, 6).unwrap());
+        assert_eq!(split.splits.len(), 3);
+        assert_eq!(split.splits[0].memo, "Vitamins");
+        assert_eq!(split.splits[2].amount.cents(), -1000);
+        let transfer = &import.transactions[2];
+        assert_eq!(transfer.amount.cents(), 20_000);
+        assert_eq!((transfer.account, transfer.transfer_to), (import.accounts[0].id, Some(import.accounts[1].id)));
+    }
+
+    #[test]
+    fn exports_what_it_imports() {
+        let (state, _) = schema::load_fixture(include_str!("../tests/fixtures/budget_data_v2.json"));
+        let file = parse(&write(&state), false).unwrap();
+        assert!(file.skipped.is_empty());
+        let import = import(&file, &[], &[], Uuid::nil(), Color32::WHITE);
+        assert_eq!(import.accounts.len(), state.accounts.len());
+        assert_eq!(import.categories.len(), state.categories.len());
+        assert_eq!(import.transactions.len(), state.transactions.len());
+        for (before, after) in state.accounts.iter().zip(&import.accounts) {
+            assert_eq!(
+                crate::model::account_balance(before, &state.transactions).cents(),
+                crate::model::account_balance(after, &import.transactions).cents()
+            );
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::money::Money;
+use crate::model::Transaction;
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Upper bound on occurrences generated for one rule in one catch-up, so a
+/// daily rule with a start date decades ago cannot stall startup.
+const MAX_CATCH_UP: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    /// Twice a month, fifteen days apart (e.g. the 15th and the 30th).
+    SemiMonthly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    pub const ALL: [Frequency; 5] = [
+        Frequency::Daily,
+        Frequency::Weekly,
+        Frequency::SemiMonthly,
+        Frequency::Monthly,
+        Frequency::Yearly,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Frequency::Daily => "Daily",
+            Frequency::Weekly => "Weekly",
+            Frequency::SemiMonthly => "Semi-monthly",
+            Frequency::Monthly => "Monthly",
+            Frequency::Yearly => "Yearly",
+        }
+    }
+}
+
+/// A template that produces a transaction on every occurrence of its schedule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringRule {
+    pub id: Uuid,
+    pub title: String,
+    pub amount: Money,
+    pub account: Uuid,
+    #[serde(default)]
+    pub category: Option<Uuid>,
+    pub frequency: Frequency,
+    pub start: NaiveDate,
+    #[serde(default)]
+    pub end: Option<NaiveDate>,
+    /// Date of the latest occurrence already turned into a transaction.
+    #[serde(default)]
+    pub last_generated: Option<NaiveDate>,
+}
+
+impl RecurringRule {
+    /// The `n`th scheduled date counting from `start` (n = 0), ignoring `end`.
+    fn nth(&self, n: usize) -> Option<NaiveDate> {
+        let n = i64::try_from(n).ok()?;
+        match self.frequency {
+            Frequency::Daily => self.start.checked_add_signed(Duration::days(n)),
+            Frequency::Weekly => self.start.checked_add_signed(Duration::weeks(n)),
+            Frequency::Monthly => add_months(self.start, n, self.start.day()),
+            Frequency::Yearly => add_months(self.start, n * 12, self.start.day()),
+            Frequency::SemiMonthly => {
+                // Two slots per month: the start day and fifteen days from it,
+                // kept in calendar order within each month.
+                let day = self.start.day();
+                let (first, second) = if day <= 15 { (day, day + 15) } else { (day - 15, day) };
+                let first_is_start = day <= 15;
+                let index = if first_is_start { n } else { n + 1 };
+                let slot_day = if index % 2 == 0 { first } else { second };
+                add_months(self.start.with_day(1)?, index / 2, slot_day)
+            }
+        }
+    }
+
+    /// Scheduled dates after `after` (or from `start` when `None`) up to and
+    /// including `until`, stopping at `end`.
+    pub fn occurrences(&self, after: Option<NaiveDate>, until: NaiveDate) -> Vec<NaiveDate> {
+        let until = self.end.map_or(until, |end| end.min(until));
+        let mut dates = Vec::new();
+        for n in 0.. {
+            let Some(date) = self.nth(n) else {
+                break;
+            };
+            if date > until || dates.len() >= MAX_CATCH_UP {
+                break;
+            }
+            if after.is_none_or(|after| date > after) {
+                dates.push(date);
+            }
+        }
+        dates
+    }
+
+    /// The next occurrence not yet generated, if the schedule has one.
+    pub fn next_due(&self) -> Option<NaiveDate> {
+        let last = self.end.unwrap_or(NaiveDate::MAX);
+        (0..)
+            .map_while(|n| self.nth(n))
+            .take_while(|date| *date <= last)
+            .find(|date| self.last_generated.is_none_or(|generated| *date > generated))
+    }
+
+    pub fn transaction_on(&self, date: NaiveDate) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            title: self.title.clone(),
+            date,
+            time: None,
+            amount: self.amount,
+            category: self.category,
+            account: self.account,
+            recurring: Some(self.id),
+            splits: Vec::new(),
+            transfer_to: None,
+            import_id: None,
+        }
+    }
+}
+
+/// Adds `months` to the month of `date`, landing on `day` or the last day of
+/// the month when it is shorter (Jan 31 → Feb 28 → Mar 31).
+fn add_months(date: NaiveDate, months: i64, day: u32) -> Option<NaiveDate> {
+    let total = i64::from(date.year()) * 12 + i64::from(date.month0()) + months;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = u32::try_from(total.rem_euclid(12)).ok()? + 1;
+    (1..=day.min(31))
+        .rev()
+        .find_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+}
+
+/// Transactions for every occurrence of `rule` due up to `today` that has not
+/// been generated yet, and the rule with `last_generated` moved forward.
+pub fn catch_up(rule: &RecurringRule, today: NaiveDate) -> (Vec<Transaction>, RecurringRule) {
+    let dates = rule.occurrences(rule.last_generated, today);
+    let mut updated = rule.clone();
+    if let Some(last) = dates.last() {
+        updated.last_generated = Some(*last);
+    }
+    let transactions = dates.into_iter().map(|date| rule.transaction_on(date)).collect();
+    (transactions, updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Currency;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn rule(frequency: Frequency, start: NaiveDate) -> RecurringRule {
+        RecurringRule {
+            id: Uuid::new_v4(),
+            title: "Rent".to_string(),
+            amount: Money::from_cents(-1_500_000, Currency::PHP),
+            account: Uuid::new_v4(),
+            category: None,
+            frequency,
+            start,
+            end: None,
+            last_generated: None,
+        }
+    }
+
+    #[test]
+    fn month_end_schedules_keep_to_the_last_day() {
+        let monthly = rule(Frequency::Monthly, day(2024, 1, 31));
+        assert_eq!(
+            monthly.occurrences(None, day(2024, 4, 30)),
+            [day(2024, 1, 31), day(2024, 2, 29), day(2024, 3, 31), day(2024, 4, 30)]
+        );
+        let yearly = rule(Frequency::Yearly, day(2024, 2, 29));
+        assert_eq!(yearly.occurrences(None, day(2025, 12, 31)), [day(2024, 2, 29), day(2025, 2, 28)]);
+    }
+
+    #[test]
+    fn semi_monthly_schedules_pay_twice_a_month() {
+        let mid_month = rule(Frequency::SemiMonthly, day(2024, 1, 15));
+        assert_eq!(
+            mid_month.occurrences(None, day(2024, 2, 29)),
+            [day(2024, 1, 15), day(2024, 1, 30), day(2024, 2, 15), day(2024, 2, 29)]
+        );
+        let month_end = rule(Frequency::SemiMonthly, day(2024, 1, 31));
+        assert_eq!(
+            month_end.occurrences(None, day(2024, 3, 16)),
+            [day(2024, 1, 31), day(2024, 2, 16), day(2024, 2, 29), day(2024, 3, 16)]
+        );
+    }
+
+    #[test]
+    fn catches_up_on_missed_runs_once() {
+        let mut monthly = rule(Frequency::Monthly, day(2024, 1, 31));
+        monthly.last_generated = Some(day(2024, 2, 29));
+        let (transactions, updated) = catch_up(&monthly, day(2024, 5, 5));
+        let dates: Vec<NaiveDate> = transactions.iter().map(|t| t.date).collect();
+        assert_eq!(dates, [day(2024, 3, 31), day(2024, 4, 30)]);
+        assert!(transactions.iter().all(|t| t.recurring == Some(monthly.id) && t.amount == monthly.amount));
+        assert_eq!(updated.last_generated, Some(day(2024, 4, 30)));
+        assert_eq!(updated.next_due(), Some(day(2024, 5, 31)));
+
+        let (again, unchanged) = catch_up(&updated, day(2024, 5, 5));
+        assert!(again.is_empty());
+        assert_eq!(unchanged.last_generated, updated.last_generated);
+
+        monthly.end = Some(day(2024, 4, 1));
+        let (ended, _) = catch_up(&monthly, day(2024, 5, 5));
+        assert_eq!(ended.len(), 1);
+        let (_, finished) = catch_up(&monthly, day(2024, 5, 5));
+        assert_eq!(finished.next_due(), None);
+    }
+}
@@ -0,0 +1,263 @@
+//! Versioning of `budget_data.json`.
+//!
+//! Every saved file records the schema version it was written with. Loading
+//! runs the file through each migration from its version up to
+//! `CURRENT_VERSION` before it is deserialized, so older files keep opening
+//! after the format changes. Files written before the version field existed
+//! count as version 0.
+//!
+//! To change the format: bump `CURRENT_VERSION`, append a step to
+//! `MIGRATIONS` that rewrites the previous shape into the new one, and add a
+//! fixture of the previous version to the tests below.
+
+use crate::date;
+use crate::model::PersistedState;
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use uuid::Uuid;
+
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Step `i` upgrades a version `i` document to version `i + 1`. Steps only
+/// touch what is still in the old shape, because unversioned files may
+/// already contain some later changes. Each returns the transactions it could
+/// only migrate by guessing.
+const MIGRATIONS: [fn(&mut Value, NaiveDate) -> Vec<Uuid>; CURRENT_VERSION as usize] = [typed_dates, exact_amounts];
+
+/// Upgrades a parsed document to `CURRENT_VERSION` in place and deserializes
+/// it. Relative dates in old files resolve against `reference`, normally the
+/// day the file was last written. The flag is true when the document changed
+/// and should be written back; transactions whose old date could not be read
+/// are listed in `unreadable_dates`.
+pub fn load(mut value: Value, reference: NaiveDate) -> Result<(PersistedState, bool), String> {
+    let version = version_of(&value)?;
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "The file uses schema version {} but this build only understands up to version {}.",
+            version, CURRENT_VERSION
+        ));
+    }
+    let unreadable_dates = upgrade(&mut value, version, reference);
+    let state = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    Ok((PersistedState { unreadable_dates, ..state }, version < CURRENT_VERSION))
+}
+
+/// Best-effort load of a document `load` rejected: every list entry and
+/// top-level field is read on its own and the ones that fail are skipped.
+/// Returns the state and how many pieces were dropped.
+pub fn load_lenient(mut value: Value, reference: NaiveDate) -> (PersistedState, usize) {
+    let version = version_of(&value).unwrap_or(0).min(CURRENT_VERSION);
+    let unreadable_dates = upgrade(&mut value, version, reference);
+
+    let mut skipped = 0;
+    let state = PersistedState {
+        version: CURRENT_VERSION,
+        monthly_budget: lenient_field(&value, "monthly_budget", &mut skipped),
+        period_budgets: lenient_field(&value, "period_budgets", &mut skipped),
+        rollover_from: lenient_field(&value, "rollover_from", &mut skipped),
+        accounts: lenient_list(&value, "accounts", &mut skipped),
+        categories: lenient_list(&value, "categories", &mut skipped),
+        transactions: lenient_list(&value, "transactions", &mut skipped),
+        recurring: lenient_list(&value, "recurring", &mut skipped),
+        unreadable_dates,
+    };
+    (state, skipped)
+}
+
+fn lenient_field<T: DeserializeOwned + Default>(value: &Value, key: &str, skipped: &mut usize) -> T {
+    let Some(raw) = value.get(key) else {
+        return T::default();
+    };
+    serde_json::from_value(raw.clone()).unwrap_or_else(|_| {
+        *skipped += 1;
+        T::default()
+    })
+}
+
+fn lenient_list<T: DeserializeOwned>(value: &Value, key: &str, skipped: &mut usize) -> Vec<T> {
+    let Some(items) = value.get(key).and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let parsed = serde_json::from_value(item.clone()).ok();
+            *skipped += usize::from(parsed.is_none());
+            parsed
+        })
+        .collect()
+}
+
+/// Runs the migrations from `version` on and stamps the current version.
+/// Returns what the steps could only guess at.
+fn upgrade(value: &mut Value, version: u32, reference: NaiveDate) -> Vec<Uuid> {
+    let guessed = MIGRATIONS[version as usize..]
+        .iter()
+        .flat_map(|step| step(value, reference))
+        .collect();
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("version".to_string(), Value::from(CURRENT_VERSION));
+    }
+    guessed
+}
+
+fn version_of(value: &Value) -> Result<u32, String> {
+    if !value.is_object() {
+        return Err("The file does not contain a budget.".to_string());
+    }
+    match value.get("version") {
+        None => Ok(0),
+        Some(raw) => raw
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("Invalid schema version {}.", raw)),
+    }
+}
+
+/// 0 → 1: free-text transaction dates ("Today", "Aug 31, 2023") become ISO
+/// dates with an optional time.
+fn typed_dates(value: &mut Value, reference: NaiveDate) -> Vec<Uuid> {
+    date::migrate_legacy_dates(value, reference)
+}
+
+/// 1 → 2: floating point amounts become exact "-458.00 PHP" strings, and the
+/// per-transaction `color` from before categories is dropped.
+fn exact_amounts(value: &mut Value, _reference: NaiveDate) -> Vec<Uuid> {
+    fn convert(slot: Option<&mut Value>) {
+        let Some(slot) = slot else {
+            return;
+        };
+        if let Some(money) = slot.as_f64().and_then(|v| Money::from_f64_lossy(v, Currency::default()))
+            && let Ok(exact) = serde_json::to_value(money)
+        {
+            *slot = exact;
+        }
+    }
+    fn each<'a>(value: &'a mut Value, key: &str) -> impl Iterator<Item = &'a mut Value> + use<'a> {
+        value
+            .get_mut(key)
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten()
+    }
+
+    convert(value.get_mut("monthly_budget"));
+    if let Some(periods) = value.get_mut("period_budgets").and_then(Value::as_object_mut) {
+        periods.values_mut().for_each(|amount| convert(Some(amount)));
+    }
+    for tx in each(value, "transactions") {
+        convert(tx.get_mut("amount"));
+        for split in each(tx, "splits") {
+            convert(split.get_mut("amount"));
+        }
+        if let Some(obj) = tx.as_object_mut() {
+            obj.remove("color");
+        }
+    }
+    for account in each(value, "accounts") {
+        convert(account.get_mut("opening_balance"));
+    }
+    for rule in each(value, "recurring") {
+        convert(rule.get_mut("amount"));
+    }
+    Vec::new()
+}
+
+/// "Today" for the fixtures: old files without dates were written then.
+#[cfg(test)]
+fn reference() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2023, 9, 1).unwrap()
+}
+
+/// Loads a file from `tests/fixtures` the way the app does, and whether it
+/// needed migrating. Shared with the tests of other modules.
+#[cfg(test)]
+pub(crate) fn load_fixture(contents: &str) -> (PersistedState, bool) {
+    let value = serde_json::from_str(contents).expect("fixture is valid JSON");
+    let (mut state, migrated) = load(value, reference()).expect("fixture loads");
+    state.normalize();
+    (state, migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::Period;
+
+    fn php(cents: i64) -> Money {
+        Money::from_cents(cents, Currency::PHP)
+    }
+
+    #[test]
+    fn loads_version_0_baseline_file() {
+        let (state, migrated) = load_fixture(include_str!("../tests/fixtures/budget_data_v0.json"));
+        assert!(migrated);
+        assert_eq!(state.version, CURRENT_VERSION);
+        assert_eq!(state.monthly_budget, php(2_000_000));
+        assert_eq!(state.transactions.len(), 3);
+
+        let shopping = &state.transactions[0];
+        assert_eq!(shopping.date, reference());
+        assert_eq!(shopping.amount, php(-45_800));
+        let coffee = &state.transactions[1];
+        assert_eq!(coffee.date, NaiveDate::from_ymd_opt(2023, 8, 31).unwrap());
+        assert_eq!(coffee.time.map(|t| t.to_string()).as_deref(), Some("18:30:00"));
+        assert_eq!(coffee.amount, php(-12_050));
+        assert_eq!(state.transactions[2].amount, php(1_500_000));
+
+        // Pre-account files get a default account and fresh ids.
+        assert_eq!(state.accounts.len(), 1);
+        assert!(state.transactions.iter().all(|t| t.account == state.accounts[0].id));
+        assert!(state.transactions.iter().all(|t| !t.id.is_nil()));
+    }
+
+    #[test]
+    fn loads_version_1_typed_dates_file() {
+        let (state, migrated) = load_fixture(include_str!("../tests/fixtures/budget_data_v1.json"));
+        assert!(migrated);
+        assert_eq!(state.monthly_budget, php(1_850_050));
+        let may = Period::of(NaiveDate::from_ymd_opt(2023, 5, 1).unwrap());
+        assert_eq!(state.period_budgets.get(&may), Some(&php(1_500_000)));
+        assert_eq!(state.accounts[0].opening_balance, php(100_000));
+        assert_eq!(state.transactions[0].amount, php(-33_333));
+        assert_eq!(state.transactions[0].category, Some(state.categories[0].id));
+        assert_eq!(state.transactions[0].date, NaiveDate::from_ymd_opt(2023, 5, 14).unwrap());
+    }
+
+    #[test]
+    fn loads_unversioned_version_2_file() {
+        let (state, migrated) = load_fixture(include_str!("../tests/fixtures/budget_data_v2_unversioned.json"));
+        // Only the version stamp is new, but the file should still be rewritten once.
+        assert!(migrated);
+        assert_eq!(state.recurring.len(), 1);
+        assert_eq!(state.recurring[0].amount, php(-1_200_000));
+        assert_eq!(state.transactions[0].splits.len(), 2);
+        assert_eq!(state.transactions[1].transfer_to, Some(state.accounts[1].id));
+    }
+
+    #[test]
+    fn current_version_file_is_not_migrated() {
+        let (state, migrated) = load_fixture(include_str!("../tests/fixtures/budget_data_v2.json"));
+        assert!(!migrated);
+        assert_eq!(state.version, CURRENT_VERSION);
+        assert_eq!(state.transactions[0].amount, php(-45_800));
+    }
+
+    #[test]
+    fn saved_state_round_trips_at_current_version() {
+        let (state, _) = load_fixture(include_str!("../tests/fixtures/budget_data_v0.json"));
+        let json = serde_json::to_value(&state).unwrap();
+        assert_eq!(json["version"], CURRENT_VERSION);
+        let (reloaded, migrated) = load(json, reference()).unwrap();
+        assert!(!migrated);
+        assert_eq!(reloaded.transactions.len(), state.transactions.len());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let value = serde_json::json!({ "version": CURRENT_VERSION + 1, "monthly_budget": "0.00 PHP", "transactions": [] });
+        assert!(load(value, reference()).unwrap_err().contains("schema version"));
+    }
+}
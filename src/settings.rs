@@ -0,0 +1,77 @@
+//! Preferences that belong to this installation rather than to a budget.
+
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How many entries the "Recent files" menu keeps.
+const RECENT_LIMIT: usize = 8;
+
+/// A named budget, such as "Personal" or "Joint", and the file it lives in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub data_file: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// How many timestamped backups of the data file to keep; 0 disables them.
+    pub backup_retention: usize,
+    /// Budget file to open instead of the default one in the data folder.
+    /// Follows the budget last switched to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<Profile>,
+    /// Budget files opened most recently, newest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recent_files: Vec<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backup_retention: 10,
+            data_file: None,
+            profiles: Vec::new(),
+            recent_files: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn path() -> PathBuf {
+        paths::config_dir().join("settings.json")
+    }
+
+    /// Missing or unreadable settings fall back to the defaults; they are
+    /// cheap to set again, unlike budget data.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn profile_for(&self, data_file: &Path) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.data_file == data_file)
+    }
+
+    /// Moves `path` to the front of the recent files.
+    pub fn remember(&mut self, path: &Path) {
+        self.recent_files.retain(|recent| recent != path);
+        self.recent_files.insert(0, path.to_path_buf());
+        self.recent_files.truncate(RECENT_LIMIT);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+        }
+        fs::write(path, json).map_err(|err| format!("Could not save settings: {}", err))
+    }
+}
@@ -0,0 +1,328 @@
+//! Budget storage in a SQLite database, written one record at a time.
+//!
+//! Each record is kept as the same JSON it has in `budget_data.json`, so the
+//! schema migrations in `schema` apply to both formats. Tables keep records
+//! in list order through `seq`.
+
+use crate::history::Touched;
+use crate::model::{BudgetData, PersistedState};
+use crate::schema;
+use crate::storage::{self, Store};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const TABLES: [&str; 4] = ["accounts", "categories", "transactions", "recurring"];
+
+#[derive(Debug)]
+pub struct SqliteStore {
+    path: PathBuf,
+    /// Opened on first use so a damaged file surfaces as a load error.
+    connection: Option<Connection>,
+}
+
+impl SqliteStore {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            connection: None,
+        }
+    }
+
+    fn connection(&mut self) -> Result<&Connection, String> {
+        if self.connection.is_none() {
+            self.connection = Some(open(&self.path)?);
+        }
+        self.connection.as_ref().ok_or_else(|| "No database connection.".to_string())
+    }
+}
+
+/// Opens (creating if needed) a database with the budget tables.
+fn open(path: &Path) -> Result<Connection, String> {
+    let describe = |err: rusqlite::Error| format!("Could not open {}: {}", path.display(), err);
+    let connection = Connection::open(path).map_err(describe)?;
+    let mut ddl = "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);".to_string();
+    for table in TABLES {
+        ddl.push_str(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (id TEXT PRIMARY KEY, seq INTEGER NOT NULL, data TEXT NOT NULL);",
+            table
+        ));
+    }
+    connection.execute_batch(&ddl).map_err(describe)?;
+    Ok(connection)
+}
+
+fn db_error(err: rusqlite::Error) -> String {
+    format!("Database error: {}", err)
+}
+
+fn budget_record(state: &PersistedState) -> Value {
+    json!({
+        "monthly_budget": state.monthly_budget,
+        "period_budgets": state.period_budgets,
+        "rollover_from": state.rollover_from,
+    })
+}
+
+fn put_meta(connection: &Connection, key: &str, value: &str) -> Result<(), String> {
+    connection
+        .execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )
+        .map(|_| ())
+        .map_err(db_error)
+}
+
+/// Inserts or updates `record` under `id`, or deletes the row when the record
+/// no longer exists. Inserts go last until `renumber` puts them in place.
+fn write_record<T: Serialize>(connection: &Connection, table: &str, id: Uuid, record: Option<&T>) -> Result<(), String> {
+    match record {
+        Some(record) => {
+            let data = serde_json::to_string(record).map_err(|e| e.to_string())?;
+            connection
+                .execute(
+                    &format!(
+                        "INSERT INTO {table} (id, seq, data) VALUES (?1, (SELECT COALESCE(MAX(seq), 0) + 1 FROM {table}), ?2) \
+                         ON CONFLICT(id) DO UPDATE SET data = excluded.data",
+                        table = table
+                    ),
+                    params![id.to_string(), data],
+                )
+                .map_err(db_error)?;
+        }
+        None => {
+            connection
+                .execute(&format!("DELETE FROM {} WHERE id = ?1", table), params![id.to_string()])
+                .map_err(db_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets every row's `seq` to its record's index, so a record restored into
+/// the middle of a list (an undone delete) reloads where it was.
+fn renumber<T>(connection: &Connection, table: &str, records: &[T], id: impl Fn(&T) -> Uuid) -> Result<(), String> {
+    let mut statement = connection
+        .prepare(&format!("UPDATE {} SET seq = ?2 WHERE id = ?1", table))
+        .map_err(db_error)?;
+    for (seq, record) in records.iter().enumerate() {
+        statement
+            .execute(params![id(record).to_string(), seq as i64])
+            .map_err(db_error)?;
+    }
+    Ok(())
+}
+
+fn read_table(connection: &Connection, table: &str) -> Result<Vec<Value>, String> {
+    let mut statement = connection
+        .prepare(&format!("SELECT data FROM {} ORDER BY seq", table))
+        .map_err(db_error)?;
+    let rows = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(db_error)?;
+    rows.map(|row| {
+        let text = row.map_err(db_error)?;
+        serde_json::from_str(&text).map_err(|err| format!("Damaged {} record: {}", table, err))
+    })
+    .collect()
+}
+
+/// Fills a fresh database at `path` with `state`.
+fn write_all(path: &Path, state: &PersistedState) -> Result<(), String> {
+    let mut connection = open(path)?;
+    let tx = connection.transaction().map_err(db_error)?;
+    put_meta(&tx, "version", &state.version.to_string())?;
+    put_meta(&tx, "budget", &budget_record(state).to_string())?;
+    fn insert_all<T: Serialize>(
+        tx: &Connection,
+        table: &str,
+        records: &[T],
+        id: impl Fn(&T) -> Uuid,
+    ) -> Result<(), String> {
+        let mut statement = tx
+            .prepare(&format!("INSERT INTO {} (id, seq, data) VALUES (?1, ?2, ?3)", table))
+            .map_err(db_error)?;
+        for (seq, record) in records.iter().enumerate() {
+            let data = serde_json::to_string(record).map_err(|e| e.to_string())?;
+            statement
+                .execute(params![id(record).to_string(), seq as i64, data])
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+    insert_all(&tx, "accounts", &state.accounts, |a| a.id)?;
+    insert_all(&tx, "categories", &state.categories, |c| c.id)?;
+    insert_all(&tx, "transactions", &state.transactions, |t| t.id)?;
+    insert_all(&tx, "recurring", &state.recurring, |r| r.id)?;
+    tx.commit().map_err(db_error)
+}
+
+impl Store for SqliteStore {
+    fn load(&mut self) -> Result<Option<(PersistedState, bool)>, String> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let reference = storage::modified_date(&self.path);
+        let path = self.path.clone();
+        let connection = self.connection()?;
+        let meta = |key: &str| {
+            connection
+                .query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| row.get::<_, String>(0))
+                .optional()
+                .map_err(db_error)
+        };
+        let Some(version) = meta("version")? else {
+            // An empty database nothing was ever saved to.
+            return Ok(None);
+        };
+        let mut document = match meta("budget")? {
+            Some(budget) => serde_json::from_str(&budget).map_err(|err| format!("Damaged budget record: {}", err))?,
+            None => json!({}),
+        };
+        let version: u32 = version
+            .parse()
+            .map_err(|_| format!("Invalid schema version \"{}\".", version))?;
+        document["version"] = Value::from(version);
+        for table in TABLES {
+            document[table] = Value::Array(read_table(connection, table)?);
+        }
+        schema::load(document, reference)
+            .map(Some)
+            .map_err(|err| format!("{} could not be loaded: {}", path.display(), err))
+    }
+
+    /// Builds the whole database in a temporary file and swaps it in, so a
+    /// failure part way leaves the previous database intact.
+    fn save(&mut self, state: &PersistedState) -> Result<(), String> {
+        let tmp = storage::sibling(&self.path, "tmp");
+        let _ = fs::remove_file(&tmp);
+        if let Err(err) = write_all(&tmp, state) {
+            let _ = fs::remove_file(&tmp);
+            return Err(err);
+        }
+        // Close our handle before replacing the file underneath it.
+        self.connection = None;
+        fs::rename(&tmp, &self.path).map_err(|err| format!("Could not save {}: {}", self.path.display(), err))
+    }
+
+    fn save_changes(&mut self, touched: &[Touched], data: &BudgetData) -> Result<(), String> {
+        let connection = self.connection()?;
+        let tx = connection.unchecked_transaction().map_err(db_error)?;
+        let mut reorder = [false; TABLES.len()];
+        for change in touched {
+            let table = match change {
+                Touched::Budget => None,
+                Touched::Account(_) => Some(0),
+                Touched::Category(_) => Some(1),
+                Touched::Transaction(_) => Some(2),
+                Touched::Rule(_) => Some(3),
+            };
+            if let Some(table) = table {
+                reorder[table] = true;
+            }
+            match *change {
+                Touched::Budget => {
+                    let budget = json!({
+                        "monthly_budget": data.budget.default_amount,
+                        "period_budgets": data.budget.periods,
+                        "rollover_from": data.budget.rollover_from,
+                    });
+                    put_meta(&tx, "budget", &budget.to_string())?;
+                }
+                Touched::Account(id) => write_record(&tx, "accounts", id, data.accounts.iter().find(|a| a.id == id))?,
+                Touched::Category(id) => {
+                    write_record(&tx, "categories", id, data.categories.iter().find(|c| c.id == id))?
+                }
+                Touched::Transaction(id) => {
+                    write_record(&tx, "transactions", id, data.transactions.iter().find(|t| t.id == id))?
+                }
+                Touched::Rule(id) => write_record(&tx, "recurring", id, data.recurring.iter().find(|r| r.id == id))?,
+            }
+        }
+        if reorder[0] {
+            renumber(&tx, "accounts", &data.accounts, |a| a.id)?;
+        }
+        if reorder[1] {
+            renumber(&tx, "categories", &data.categories, |c| c.id)?;
+        }
+        if reorder[2] {
+            renumber(&tx, "transactions", &data.transactions, |t| t.id)?;
+        }
+        if reorder[3] {
+            renumber(&tx, "recurring", &data.recurring, |r| r.id)?;
+        }
+        tx.commit().map_err(db_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{Command, History};
+    use crate::model::Transaction;
+
+    fn fixture() -> BudgetData {
+        schema::load_fixture(include_str!("../tests/fixtures/budget_data_v2.json")).0.into()
+    }
+
+    #[test]
+    fn incremental_changes_survive_a_reload() {
+        let path = std::env::temp_dir().join(format!("dybudget-{}.sqlite", Uuid::new_v4()));
+        let mut data = fixture();
+        let mut store = SqliteStore::new(&path);
+        store.save(&PersistedState::from(&data)).unwrap();
+
+        let removed = data.transactions.remove(0);
+        let mut added: Transaction = removed.clone();
+        added.id = Uuid::new_v4();
+        added.title = "Added later".to_string();
+        data.transactions.push(added.clone());
+        data.accounts[0].name = "Renamed".to_string();
+        let touched = [
+            Touched::Transaction(removed.id),
+            Touched::Transaction(added.id),
+            Touched::Account(data.accounts[0].id),
+        ];
+        store.save_changes(&touched, &data).unwrap();
+
+        let (loaded, migrated) = SqliteStore::new(&path).load().unwrap().unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(!migrated);
+        assert_eq!(loaded.accounts[0].name, "Renamed");
+        assert!(loaded.transactions.iter().all(|t| t.id != removed.id));
+        assert_eq!(loaded.transactions.last().map(|t| t.title.as_str()), Some("Added later"));
+        assert_eq!(loaded.transactions.len(), data.transactions.len());
+    }
+
+    #[test]
+    fn an_undone_delete_reloads_in_its_place() {
+        let path = std::env::temp_dir().join(format!("dybudget-{}.sqlite", Uuid::new_v4()));
+        let mut data = fixture();
+        for title in ["Second", "Third"] {
+            let mut extra = data.transactions[0].clone();
+            extra.id = Uuid::new_v4();
+            extra.title = title.to_string();
+            data.transactions.push(extra);
+        }
+        let mut store = SqliteStore::new(&path);
+        store.save(&PersistedState::from(&data)).unwrap();
+        let order: Vec<Uuid> = data.transactions.iter().map(|t| t.id).collect();
+
+        let mut history = History::default();
+        let tx = data.transactions[1].clone();
+        let command = Command::DeleteTransaction { tx, index: 1 };
+        let touched = command.touched();
+        history.execute(command, &mut data);
+        store.save_changes(&touched, &data).unwrap();
+        history.undo(&mut data).unwrap();
+        store.save_changes(&touched, &data).unwrap();
+
+        let (loaded, _) = SqliteStore::new(&path).load().unwrap().unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.transactions.iter().map(|t| t.id).collect::<Vec<_>>(), order);
+    }
+}
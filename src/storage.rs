@@ -0,0 +1,514 @@
+//! Reading and writing the budget file on disk.
+
+use crate::crypto::{self, Key};
+use crate::date;
+use crate::history::Touched;
+use crate::model::{BudgetData, PersistedState};
+use crate::schema;
+use crate::sqlite_store::SqliteStore;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Saves happen after every edit, so a backup is only taken when the newest
+/// one is at least this old.
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Timestamp in backup names; it sorts chronologically.
+const BACKUP_STAMP: &str = "%Y%m%d-%H%M%S";
+
+/// Loads and upgrades the file at `path`, decrypting it with `key` if it is
+/// encrypted. `Ok(None)` means there is no file yet; any other failure is an
+/// error so the caller never mistakes a broken file for a missing one. The
+/// flag is true when the file was migrated.
+pub fn read(path: &Path, key: Option<&Key>) -> Result<Option<(PersistedState, bool)>, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Could not read {}: {}", path.display(), err)),
+    };
+    parse(path, &plaintext(path, bytes, key)?).map(Some)
+}
+
+/// True when the file at `path` needs a passphrase to open.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut magic = [0; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| crypto::is_encrypted(&magic))
+}
+
+fn plaintext(path: &Path, bytes: Vec<u8>, key: Option<&Key>) -> Result<String, String> {
+    let bytes = match key {
+        _ if !crypto::is_encrypted(&bytes) => bytes,
+        Some(key) => key
+            .decrypt(&bytes)
+            .map_err(|err| format!("{} could not be decrypted: {}", path.display(), err))?,
+        None => return Err(format!("{} is protected by a passphrase.", path.display())),
+    };
+    String::from_utf8(bytes).map_err(|_| format!("{} is not a text file.", path.display()))
+}
+
+fn parse(path: &Path, contents: &str) -> Result<(PersistedState, bool), String> {
+    let value: Value = serde_json::from_str(contents)
+        .map_err(|err| format!("{} is not valid JSON: {}", path.display(), err))?;
+    schema::load(value, modified_date(path)).map_err(|err| format!("{} could not be loaded: {}", path.display(), err))
+}
+
+/// Salvages what it can from a file `read` rejected. Broken JSON is cut back
+/// to the last complete entry; entries that do not deserialize are skipped.
+/// Returns the state and how many pieces were dropped.
+pub fn read_lenient(path: &Path, key: Option<&Key>) -> Result<(PersistedState, usize), String> {
+    let bytes = fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    let contents = plaintext(path, bytes, key)?;
+    let (value, truncated) = match serde_json::from_str(&contents) {
+        Ok(value) => (value, false),
+        Err(_) => (
+            salvage_json(&contents).ok_or_else(|| "Nothing in the file could be recovered.".to_string())?,
+            true,
+        ),
+    };
+    let (state, skipped) = schema::load_lenient(value, modified_date(path));
+    Ok((state, skipped + usize::from(truncated)))
+}
+
+/// Saves `state` to `path` without ever leaving a half-written file: the JSON
+/// goes to a temporary file that replaces the original only once it is
+/// complete. Before that the previous version is backed up when one is due,
+/// keeping the newest `retention` backups. With a `key` the file is encrypted.
+pub fn write(path: &Path, state: &PersistedState, retention: usize, key: Option<&Key>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    let bytes = match key {
+        Some(key) => key.encrypt(json.as_bytes())?,
+        None => json.into_bytes(),
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+    }
+    let backup = if retention > 0 { back_up(path, retention) } else { Ok(()) };
+    write_atomic(path, &bytes).map_err(|err| format!("Could not save {}: {}", path.display(), err))?;
+    backup.map_err(|err| format!("Saved, but the backup failed: {}", err))
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp = sibling(path, "tmp");
+    let result = File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Somewhere a budget is kept. `open` picks the implementation from the file
+/// extension.
+pub trait Store: std::fmt::Debug {
+    /// Same contract as `read`: `Ok(None)` means nothing has been saved yet
+    /// and the flag is true when the stored data was migrated.
+    fn load(&mut self) -> Result<Option<(PersistedState, bool)>, String>;
+
+    /// Replaces everything stored with `state`.
+    fn save(&mut self, state: &PersistedState) -> Result<(), String>;
+
+    /// Writes just the records in `touched` as they now are in `data`.
+    /// Stores that cannot write part of a budget save all of it.
+    fn save_changes(&mut self, touched: &[Touched], data: &BudgetData) -> Result<(), String> {
+        let _ = touched;
+        self.save(&PersistedState::from(data))
+    }
+
+    fn set_backup_retention(&mut self, _retention: usize) {}
+
+    /// Decrypts a passphrase-protected budget and loads it. The key is kept
+    /// for later saves.
+    fn unlock(&mut self, _passphrase: &str) -> Result<Option<(PersistedState, bool)>, String> {
+        Err("This kind of budget cannot be encrypted.".to_string())
+    }
+
+    /// Encrypts future saves with `passphrase`, or stops encrypting with
+    /// `None`. Takes effect on the next save.
+    fn set_passphrase(&mut self, _passphrase: Option<&str>) -> Result<(), String> {
+        Err("Only JSON budgets can be protected with a passphrase; convert this one to JSON first.".to_string())
+    }
+
+    /// Key the budget is encrypted with, if any.
+    fn key(&self) -> Option<&Key> {
+        None
+    }
+}
+
+/// The budget as one JSON document, rewritten on every save and optionally
+/// encrypted.
+#[derive(Debug)]
+pub struct JsonStore {
+    path: PathBuf,
+    retention: usize,
+    key: Option<Key>,
+}
+
+impl Store for JsonStore {
+    fn load(&mut self) -> Result<Option<(PersistedState, bool)>, String> {
+        read(&self.path, self.key.as_ref())
+    }
+
+    fn save(&mut self, state: &PersistedState) -> Result<(), String> {
+        write(&self.path, state, self.retention, self.key.as_ref())
+    }
+
+    fn set_backup_retention(&mut self, retention: usize) {
+        self.retention = retention;
+    }
+
+    fn unlock(&mut self, passphrase: &str) -> Result<Option<(PersistedState, bool)>, String> {
+        let bytes = fs::read(&self.path).map_err(|err| format!("Could not read {}: {}", self.path.display(), err))?;
+        let (contents, key) = crypto::decrypt(&bytes, passphrase)?;
+        self.key = Some(key);
+        let contents = String::from_utf8(contents).map_err(|_| format!("{} is not a text file.", self.path.display()))?;
+        parse(&self.path, &contents).map(Some)
+    }
+
+    fn set_passphrase(&mut self, passphrase: Option<&str>) -> Result<(), String> {
+        self.key = passphrase.map(Key::new).transpose()?;
+        Ok(())
+    }
+
+    fn key(&self) -> Option<&Key> {
+        self.key.as_ref()
+    }
+}
+
+/// True for paths that `open` keeps in SQLite rather than JSON.
+pub fn is_sqlite(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ["sqlite", "sqlite3", "db"].contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// The store for `path`: SQLite for `.sqlite`, `.sqlite3` and `.db` files,
+/// JSON otherwise. Backups are only taken of JSON files.
+pub fn open(path: &Path, retention: usize) -> Box<dyn Store> {
+    if is_sqlite(path) {
+        Box::new(SqliteStore::new(path))
+    } else {
+        Box::new(JsonStore {
+            path: path.to_path_buf(),
+            retention,
+            key: None,
+        })
+    }
+}
+
+/// Loads another budget file, JSON or SQLite, without opening it as the
+/// current budget. Encrypted files open only if `key` is the one they were
+/// sealed with.
+pub fn load_file(path: &Path, key: Option<&Key>) -> Result<PersistedState, String> {
+    let loaded = if is_sqlite(path) {
+        SqliteStore::new(path).load()?
+    } else {
+        read(path, key)?
+    };
+    let (mut state, _) = loaded.ok_or_else(|| format!("{} does not exist.", path.display()))?;
+    state.normalize();
+    Ok(state)
+}
+
+/// Copies the budget in `from` to the new file `to`, converting between JSON
+/// and SQLite according to their extensions. Returns how many transactions
+/// were copied.
+pub fn convert(from: &Path, to: &Path) -> Result<usize, String> {
+    if to.exists() {
+        return Err(format!("{} already exists.", to.display()));
+    }
+    let state = load_file(from, None)?;
+    if let Some(dir) = to.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+    }
+    open(to, 0).save(&state)?;
+    Ok(state.transactions.len())
+}
+
+/// Folder the timestamped backups of `path` are kept in.
+pub fn backups_dir(path: &Path) -> PathBuf {
+    path.with_file_name("backups")
+}
+
+/// Backups of `path`, newest first.
+pub fn backups(path: &Path) -> Vec<PathBuf> {
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(backups_dir(path)) else {
+        return Vec::new();
+    };
+    let mut found: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|backup| {
+            let name = backup.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            is_backup_name(name, stem)
+        })
+        .collect();
+    // The timestamp in the name sorts chronologically.
+    found.sort();
+    found.reverse();
+    found
+}
+
+/// Whether `name` is `<stem>-YYYYMMDD-HHMMSS.json`, so that the backups of
+/// "personal" are not confused with those of "personal-2".
+fn is_backup_name(name: &str, stem: &str) -> bool {
+    name.strip_prefix(stem)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|rest| rest.strip_suffix(".json"))
+        .is_some_and(|stamp| NaiveDateTime::parse_from_str(stamp, BACKUP_STAMP).is_ok())
+}
+
+/// Copies the current file into the backups folder if the newest backup is
+/// older than `BACKUP_INTERVAL`, then drops all but the newest `retention`.
+fn back_up(path: &Path, retention: usize) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let existing = backups(path);
+    let due = existing
+        .first()
+        .and_then(|newest| fs::metadata(newest).and_then(|m| m.modified()).ok())
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_none_or(|age| age >= BACKUP_INTERVAL);
+    if due {
+        let dir = backups_dir(path);
+        fs::create_dir_all(&dir).map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("budget_data");
+        let stamp = chrono::Local::now().format(BACKUP_STAMP);
+        let backup = dir.join(format!("{}-{}.json", stem, stamp));
+        fs::copy(path, &backup).map_err(|err| format!("Could not write {}: {}", backup.display(), err))?;
+    }
+    for old in backups(path).into_iter().skip(retention) {
+        fs::remove_file(&old).map_err(|err| format!("Could not remove {}: {}", old.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Copies an unreadable file to `<name>.corrupt` (or a timestamped variant if
+/// that exists already) so nothing is lost when the app writes over it.
+pub fn preserve_corrupt(path: &Path) -> Result<PathBuf, String> {
+    let mut copy = sibling(path, "corrupt");
+    if copy.exists() {
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        copy = sibling(path, &format!("{}.corrupt", stamp));
+    }
+    fs::copy(path, &copy)
+        .map(|_| copy)
+        .map_err(|err| format!("Could not copy {}: {}", path.display(), err))
+}
+
+/// Backups of `path` followed by other files next to it that look like
+/// earlier copies, newest first. Corrupt copies are left out.
+pub fn backup_candidates(path: &Path) -> Vec<PathBuf> {
+    let mut candidates = backups(path);
+    candidates.extend(sibling_copies(path));
+    candidates
+}
+
+/// Timestamped backups left next to `path` and copies named like
+/// `<file name>.bak`.
+fn sibling_copies(path: &Path) -> Vec<PathBuf> {
+    let (Some(stem), Some(file_name)) = (
+        path.file_stem().and_then(|s| s.to_str()),
+        path.file_name().and_then(|s| s.to_str()),
+    ) else {
+        return Vec::new();
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut found: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|candidate| {
+            let name = candidate.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            let copy = name.strip_prefix(file_name).is_some_and(|rest| rest.starts_with('.'));
+            candidate.is_file()
+                && (copy || is_backup_name(name, stem))
+                && !name.ends_with(".corrupt")
+                && !name.ends_with(".tmp")
+        })
+        .filter_map(|candidate| {
+            let modified = fs::metadata(&candidate).and_then(|m| m.modified()).ok()?;
+            Some((modified, candidate))
+        })
+        .collect();
+    found.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    found.into_iter().map(|(_, path)| path).collect()
+}
+
+pub(crate) fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Modification time and size of `path`, compared to notice when another
+/// program has written to it. `None` when the file does not exist.
+pub fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// "Today" in an old file meant the day it was written, not the day it is read.
+pub(crate) fn modified_date(path: &Path) -> NaiveDate {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .map(|modified| chrono::DateTime::<chrono::Local>::from(modified).date_naive())
+        .unwrap_or_else(|_| date::today())
+}
+
+/// Repairs truncated or damaged JSON by cutting it after the latest complete
+/// value that still leaves a parseable document once the open brackets are
+/// closed again.
+fn salvage_json(text: &str) -> Option<Value> {
+    // Byte offsets just past each closing bracket, with the brackets still
+    // open at that point.
+    let mut cuts: Vec<(usize, Vec<u8>)> = Vec::new();
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, byte) in text.bytes().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => open.push(byte),
+            b'}' | b']' => {
+                open.pop();
+                cuts.push((i + 1, open.clone()));
+            }
+            _ => {}
+        }
+    }
+
+    cuts.iter().rev().take(500).find_map(|(end, open)| {
+        let mut candidate = text[..*end].to_string();
+        for bracket in open.iter().rev() {
+            candidate.push(if *bracket == b'{' { '}' } else { ']' });
+        }
+        serde_json::from_str::<Value>(&candidate).ok().filter(Value::is_object)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    /// An empty folder of its own under the system temp dir.
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dybudget-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn fixture() -> PersistedState {
+        schema::load_fixture(include_str!("../tests/fixtures/budget_data_v2.json")).0
+    }
+
+    /// A file in the backups folder of `path`, dated `age` ago.
+    fn old_backup(path: &Path, name: &str, age: Duration) -> PathBuf {
+        let backup = backups_dir(path).join(name);
+        fs::create_dir_all(backups_dir(path)).unwrap();
+        fs::write(&backup, "{}").unwrap();
+        File::options()
+            .write(true)
+            .open(&backup)
+            .and_then(|file| file.set_modified(SystemTime::now() - age))
+            .unwrap();
+        backup
+    }
+
+    #[test]
+    fn writes_atomically() {
+        let dir = scratch_dir();
+        let path = dir.join("personal.json");
+        let state = fixture();
+        write(&path, &state, 0, None).unwrap();
+        let (read_back, migrated) = read(&path, None).unwrap().unwrap();
+        assert!(!migrated);
+        assert_eq!(read_back.transactions.len(), state.transactions.len());
+        assert!(!sibling(&path, "tmp").exists());
+
+        // A write that cannot finish leaves the previous file as it was.
+        let before = fs::read(&path).unwrap();
+        fs::create_dir(sibling(&path, "tmp")).unwrap();
+        assert!(write(&path, &state, 0, None).is_err());
+        assert_eq!(fs::read(&path).unwrap(), before);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_backups_of_this_budget_only() {
+        let dir = scratch_dir();
+        let path = dir.join("personal.json");
+        let state = fixture();
+        write(&path, &state, 3, None).unwrap();
+        assert!(backups(&path).is_empty(), "nothing to back up before the first save");
+
+        let day = Duration::from_secs(24 * 60 * 60);
+        let oldest = old_backup(&path, "personal-20240101-080000.json", day * 3);
+        let older = old_backup(&path, "personal-20240102-080000.json", day * 2);
+        let old = old_backup(&path, "personal-20240103-080000.json", day);
+        let other_budget = old_backup(&path, "personal-2-20240101-080000.json", day * 3);
+        let unrelated = old_backup(&path, "personal-notes.json", day * 3);
+
+        write(&path, &state, 3, None).unwrap();
+        let kept = backups(&path);
+        assert_eq!(kept.len(), 3);
+        assert!(!kept.contains(&oldest) && !oldest.exists());
+        assert_eq!(&kept[1..], [old, older]);
+        assert!(other_budget.exists() && unrelated.exists());
+
+        // The newest backup is recent now, so the next save does not add one.
+        write(&path, &state, 3, None).unwrap();
+        assert_eq!(backups(&path), kept);
+
+        let copy = sibling(&path, "bak");
+        fs::write(&copy, "{}").unwrap();
+        fs::write(dir.join("personal-2.json"), "{}").unwrap();
+        let candidates = backup_candidates(&path);
+        assert_eq!(candidates.len(), 4);
+        assert_eq!(candidates[3], copy);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn salvages_truncated_json() {
+        let text = r#"{"title": "a } in [text]", "list": [{"x": 1}, {"x": 2}, {"x": "#;
+        assert_eq!(
+            salvage_json(text),
+            Some(json!({"title": "a } in [text]", "list": [{"x": 1}, {"x": 2}]}))
+        );
+        let escaped = r#"{"quote": "say \"}\"", "n": [[1], [2"#;
+        assert_eq!(salvage_json(escaped), Some(json!({"quote": "say \"}\"", "n": [[1]]})));
+        assert_eq!(salvage_json("not json"), None);
+        assert_eq!(salvage_json("[1, [2]"), None, "only an object is a budget");
+    }
+}